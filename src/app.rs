@@ -1,18 +1,41 @@
-use std::thread::{JoinHandle, sleep};
+use std::thread::JoinHandle;
 use std::time;
 use std::{sync::Arc, time::Instant};
 
-use crate::config::Settings;
+use crate::appearance::{self, Scheme};
+use crate::audio::Audio;
+use crate::config::{Behavior, Profile, Settings, Watcher};
 use crate::layer::{Wire, gpu};
-use crossbeam::channel::{Receiver, Sender};
+use crate::metrics::Metrics;
+use crate::notifications::Notifications;
+use crate::timer::{Deadline, Timers};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
 use derive_getters::Getters;
 
 use super::{layer::Layer, shield::Shield};
 
+// Interval between two frames while the shield animates between values.
+const FRAME_INTERVAL: time::Duration = time::Duration::from_millis(16);
+// Scrolling over the shield is applied at most once per interval.
+const ADJUST_INTERVAL: time::Duration = time::Duration::from_millis(40);
+// Saving a file fires several events, the configuration is read once they settled.
+const RELOAD_DELAY: time::Duration = time::Duration::from_millis(100);
+
 #[derive(Debug)]
 pub enum Event {
     VolumeChanged(f32),
+    // The sink was muted or unmuted, the value is the level to show.
+    MuteChanged(f32),
+    // Another sink became the default one, the value is its level.
+    DeviceChanged(f32),
+    // Level sent as a notification by another program, e.g. a keybinding script.
+    Notified(Profile, f32),
+    SchemeChanged(Scheme),
+    // Change of the volume asked by scrolling over the shield.
+    AdjustVolume(f32),
     Hide,
+    // A configuration file changed, or a reload was asked with SIGHUP or over IPC.
+    Reload,
 }
 
 #[derive(Getters)]
@@ -23,7 +46,29 @@ pub struct App {
     sender: Sender<Event>,
     handles: Vec<JoinHandle<()>>,
     transmitter: Option<Sender<gpu::Event>>,
+    // The layer is created with the first notification and kept afterward,
+    // hiding only unmaps its surface.
     wire: Option<Wire>,
+    visible: bool,
+    audio: Option<Audio>,
+    // Watches the files of the settings, the theme may change with a reload.
+    watcher: Option<Watcher>,
+    // Serves the levels sent as notifications, those shown expire when the
+    // shield hides.
+    notifications: Option<Notifications>,
+    // The color scheme of the desktop is monitored, which starts as soon as
+    // the settings follow it and doesn't stop afterward.
+    following: bool,
+    timers: Timers,
+    // Change of the volume waiting for the debounce deadline.
+    adjustment: f32,
+    // When the last frame was drawn.
+    drawn: Option<Instant>,
+    metrics: Arc<Metrics>,
+    // Prints the metrics every time the shield hides.
+    verbose: bool,
+    // Reloaded settings with problems are refused rather than applied.
+    strict: bool,
 }
 
 impl App {
@@ -38,6 +83,17 @@ impl App {
             transmitter: None,
             shield: Shield::new(settings),
             wire: None,
+            visible: false,
+            audio: None,
+            watcher: None,
+            notifications: None,
+            following: false,
+            timers: Timers::default(),
+            adjustment: 0.0,
+            drawn: None,
+            metrics: Arc::new(Metrics::default()),
+            verbose: false,
+            strict: false,
         }
     }
 
@@ -49,55 +105,352 @@ impl App {
         self.settings.size().1
     }
 
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
     pub fn register_handle(&mut self, handle: JoinHandle<()>) {
         self.handles.push(handle);
     }
 
-    pub fn volume_changed(&mut self, volume: f32, timer_tx: Sender<Instant>) {
-        let wire = match self.wire.as_mut() {
-            Some(wire) => wire,
-            None => {
-                let layer = Layer::new(self.settings.clone()).unwrap();
-                self.wire = Some(layer.run().unwrap());
-                self.wire.as_mut().unwrap()
+    // The audio backend is used to change the volume when the shield is interactive.
+    pub fn register_audio(&mut self, audio: Audio) {
+        self.audio = Some(audio);
+    }
+
+    pub fn register_watcher(&mut self, watcher: Watcher) {
+        self.watcher = Some(watcher);
+    }
+
+    pub fn register_notifications(&mut self, notifications: Notifications) {
+        self.notifications = Some(notifications);
+    }
+
+    // Monitors the color scheme of the desktop when the settings follow it and
+    // it isn't monitored yet. The scheme is ignored by the shield while they
+    // don't follow it anymore.
+    pub fn follow_system_scheme(&mut self) {
+        if self.following || !*self.settings.follow_system() {
+            return;
+        }
+
+        match appearance::Portal::new() {
+            Ok(portal) => {
+                let handle = appearance::monitor(portal, self.sender.clone());
+                self.handles.push(handle);
+                self.following = true;
             }
-        };
+            Err(err) => eprintln!("Color scheme can't be followed: {err:?}"),
+        }
+    }
+
+    // Scrolling produces many small changes, they add up until the debounce
+    // deadline and are sent to the audio backend at once.
+    fn adjust_volume(&mut self, delta: f32) {
+        self.adjustment += delta;
+        if !self.timers.is_set(Deadline::Debounce) {
+            self.timers.set_after(Deadline::Debounce, ADJUST_INTERVAL);
+        }
+    }
 
-        let scene = self.shield.scene(volume);
-        wire.sender()
-            .send(crate::layer::gpu::Event::Render(scene))
-            .unwrap();
+    fn apply_adjustment(&mut self) {
+        let delta = std::mem::take(&mut self.adjustment);
+        let Some(audio) = self.audio.as_mut() else {
+            return;
+        };
 
-        wire.sender().send(crate::layer::gpu::Event::Paint).unwrap();
-        timer_tx
-            .send(Instant::now() + time::Duration::from_millis(750))
-            .unwrap();
+        if let Err(err) = audio.adjust(delta) {
+            eprintln!("Volume couldn't be changed: {err:?}");
+        }
     }
 
-    pub fn start(mut self) {
-        let (timer_tx, timer_rx) = crossbeam::channel::unbounded();
-        let sender = self.sender.clone();
-        let countdown = std::thread::spawn(move || {
-            while let Ok(instant) = timer_rx.recv() {
-                let duration = instant - Instant::now();
-                sleep(duration);
-                if timer_rx.is_empty() {
-                    sender.send(Event::Hide).unwrap();
+    // The event is shown with the settings of its profile. When the profile
+    // differs from the one of the previous event, the layer gets its settings
+    // before showing, so the surface has the size and place of the profile.
+    fn show(&mut self, profile: Profile, volume: f32) {
+        let was_visible = self.visible;
+        let switched = self.shield.set_profile(profile);
+        let settings = self.shield.settings().clone();
+
+        if switched {
+            self.send(gpu::Event::Settings(settings.clone()));
+        }
+        if !self.visible {
+            self.send(gpu::Event::Show);
+        }
+        if self.wire.is_none() {
+            // Without a layer there is nothing to show, the next event tries
+            // again, e.g. once the compositor is back.
+            match Layer::new(settings.clone(), self.sender.clone()).and_then(Layer::run) {
+                Ok(wire) => self.wire = Some(wire),
+                Err(err) => {
+                    eprintln!("Shield can't be shown: {err:?}");
+                    return;
                 }
             }
-        });
-
-        while let Ok(event) = self.receiver.recv() {
-            match event {
-                Event::VolumeChanged(volume) => self.volume_changed(volume, timer_tx.clone()),
-                Event::Hide => {
-                    if let Some(mut wire) = self.wire.take() {
-                        wire.sender().send(crate::layer::gpu::Event::Terminate);
-                    }
-                }
+        }
+        self.visible = true;
+
+        self.shield.animate_to(volume);
+        self.request_frame();
+
+        if let Some(timeout) = hide_after(settings.behavior(), profile, was_visible, switched) {
+            self.timers.set(Deadline::Hide, Instant::now() + timeout);
+        }
+    }
+
+    // The settings of the app stay as configured, the shield applies the
+    // colors of the new scheme to them.
+    pub fn scheme_changed(&mut self, scheme: Scheme) {
+        self.shield.set_scheme(scheme);
+        self.request_frame();
+    }
+
+    // Invalid configurations are reported and the current settings are kept,
+    // so a typo while editing doesn't bring the shield down.
+    fn reload(&mut self) {
+        let settings = match self
+            .settings
+            .reload()
+            .and_then(|settings| settings.report(self.strict).map(|_| settings))
+        {
+            Ok(settings) => Arc::new(settings),
+            Err(err) => {
+                eprintln!("Configuration couldn't be reloaded: {err:?}");
+                return;
             }
+        };
+        if self.verbose {
+            eprintln!("{}", settings.loaded());
+        }
+
+        if let Some(watcher) = self.watcher.as_ref() {
+            watcher.set_sources(settings.sources());
+        }
+        self.settings = settings.clone();
+        self.shield.set_settings(settings);
+        self.follow_system_scheme();
+
+        self.send(gpu::Event::Settings(self.shield.settings().clone()));
+        if self.visible {
+            self.request_frame();
+        }
+    }
+
+    fn hide(&mut self) {
+        self.timers.cancel(Deadline::Hide);
+
+        if self.visible {
+            self.send(gpu::Event::Hide);
+            self.visible = false;
+
+            if let Some(notifications) = self.notifications.as_ref() {
+                notifications.expire();
+            }
+
+            if self.verbose {
+                eprintln!("{}", self.metrics.report());
+            }
+        }
+    }
+
+    // Changes coming in faster than frames are drawn only update the target of
+    // the shield, the frame drawn next shows the latest of them.
+    fn request_frame(&mut self) {
+        if self.timers.is_set(Deadline::Frame) {
+            return;
         }
 
-        let _ = countdown.join();
+        let now = Instant::now();
+        let at = self
+            .drawn
+            .map_or(now, |drawn| (drawn + FRAME_INTERVAL).max(now));
+        self.timers.set(Deadline::Frame, at);
+    }
+
+    // Renders the current frame of the shield and presents it. While a
+    // transition is running, the next frame is scheduled.
+    fn render(&mut self) {
+        if self.wire.is_none() {
+            return;
+        }
+
+        if self.shield.animating() && !self.timers.is_set(Deadline::Frame) {
+            self.timers.set_after(Deadline::Frame, FRAME_INTERVAL);
+        }
+
+        self.drawn = Some(Instant::now());
+        self.metrics.frame_drawn();
+
+        let drawing = self.shield.frame();
+        if self.send(gpu::Event::Render(drawing)) {
+            self.send(gpu::Event::Paint);
+        }
+    }
+
+    // Sending fails once the layer thread stopped, e.g. when the connection to
+    // the compositor was lost. The layer is dropped then and created again with
+    // the next event shown.
+    fn send(&mut self, event: gpu::Event) -> bool {
+        let Some(wire) = self.wire.as_mut() else {
+            return false;
+        };
+        if wire.sender().send(event).is_ok() {
+            return true;
+        }
+
+        eprintln!("Layer stopped, it's created again with the next event");
+        self.wire = None;
+        self.visible = false;
+        false
+    }
+
+    fn expire(&mut self, deadline: Deadline) {
+        match deadline {
+            Deadline::Hide => self.hide(),
+            Deadline::Frame => self.render(),
+            Deadline::Debounce => self.apply_adjustment(),
+            Deadline::Reload => self.reload(),
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::VolumeChanged(volume) => self.show(Profile::Volume, volume),
+            Event::MuteChanged(volume) => self.show(Profile::Mute, volume),
+            Event::DeviceChanged(volume) => self.show(Profile::Device, volume),
+            Event::Notified(profile, level) => self.show(profile, level),
+            Event::SchemeChanged(scheme) => self.scheme_changed(scheme),
+            Event::AdjustVolume(delta) => self.adjust_volume(delta),
+            Event::Hide => self.hide(),
+            Event::Reload => self.timers.set_after(Deadline::Reload, RELOAD_DELAY),
+        }
+    }
+
+    // The loop sleeps until the next event or the next deadline, whichever
+    // comes first. Deadlines are expired before waiting again so a steady
+    // stream of events can't hold them back.
+    pub fn start(mut self) {
+        loop {
+            for deadline in self.timers.expired(Instant::now()) {
+                self.expire(deadline);
+            }
+
+            let event = match self.timers.next() {
+                Some(at) => match self.receiver.recv_deadline(at) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match self.receiver.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
+
+            self.handle(event);
+        }
+    }
+}
+
+// When the shield shown for the event hides, if it hides on its own. Mute and
+// device changes stay on screen for their own timeout. An event of another kind
+// counts as a new one, it's shown for its whole timeout even when the shield
+// was already visible. Otherwise the timeout only restarts when pinned.
+fn hide_after(
+    behavior: &Behavior,
+    profile: Profile,
+    was_visible: bool,
+    switched: bool,
+) -> Option<time::Duration> {
+    if !*behavior.auto_hide() || !(*behavior.pin() || !was_visible || switched) {
+        return None;
+    }
+
+    match profile {
+        Profile::Volume => Some(*behavior.timeout()),
+        _ => Some(*behavior.event_timeout()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    fn behavior(toml: &str) -> Behavior {
+        *Settings::from_toml(&format!(
+            "[behavior]\ntimeout_ms = 1000\nevent_timeout_ms = 3000\n{toml}"
+        ))
+        .unwrap()
+        .behavior()
+    }
+
+    #[test]
+    fn events_are_hidden_after_the_timeout_of_their_profile() {
+        let behavior = behavior("");
+
+        assert_eq!(
+            hide_after(&behavior, Profile::Volume, false, false),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(
+            hide_after(&behavior, Profile::Mute, false, true),
+            Some(Duration::from_millis(3000))
+        );
+    }
+
+    #[test]
+    fn timeout_restarts_only_when_pinned_or_switched() {
+        let unpinned = behavior("pin = false\n");
+        let pinned = behavior("pin = true\n");
+
+        assert_eq!(hide_after(&unpinned, Profile::Volume, true, false), None);
+        assert_eq!(
+            hide_after(&unpinned, Profile::Device, true, true),
+            Some(Duration::from_millis(3000))
+        );
+        assert_eq!(
+            hide_after(&pinned, Profile::Volume, true, false),
+            Some(Duration::from_millis(1000))
+        );
+    }
+
+    #[test]
+    fn frames_requested_before_the_next_one_are_coalesced() {
+        let mut app = App::new(Settings::from_toml("").unwrap());
+        let drawn = Instant::now();
+        app.drawn = Some(drawn);
+
+        app.request_frame();
+        app.request_frame();
+        app.request_frame();
+
+        // A single frame is drawn, one interval after the last one.
+        assert_eq!(app.timers.next(), Some(drawn + FRAME_INTERVAL));
+        assert_eq!(
+            app.timers.expired(drawn + FRAME_INTERVAL),
+            vec![Deadline::Frame]
+        );
+        assert!(!app.timers.is_set(Deadline::Frame));
+
+        // Without a recent frame, the next one is drawn right away.
+        app.drawn = Some(drawn - FRAME_INTERVAL * 2);
+        let before = Instant::now();
+        app.request_frame();
+        let at = app.timers.next().unwrap();
+        assert!(at >= before && at <= Instant::now());
+    }
+
+    #[test]
+    fn shield_stays_without_auto_hide() {
+        let behavior = behavior("auto_hide = false\npin = true\n");
+
+        assert_eq!(hide_after(&behavior, Profile::Volume, false, false), None);
+        assert_eq!(hide_after(&behavior, Profile::Mute, false, true), None);
     }
 }
@@ -0,0 +1,162 @@
+use anyhow::{Result, anyhow};
+use crossbeam::channel::Sender;
+use std::thread::JoinHandle;
+use zbus::blocking::{Connection, Proxy, proxy::SignalIterator};
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::app::Event;
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+const INTERFACE: &str = "org.freedesktop.portal.Settings";
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+// Values defined by the freedesktop appearance specification for color-scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl From<u32> for Scheme {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => Scheme::Dark,
+            2 => Scheme::Light,
+            _ => Scheme::NoPreference,
+        }
+    }
+}
+
+// Source of the color scheme preferred by the user. The desktop portal is the
+// source used by shield, tests use a stand-in that doesn't need a session bus.
+pub trait Source {
+    fn scheme(&mut self) -> Result<Scheme>;
+
+    // Blocks until the color scheme changes. None is returned when the
+    // source won't report any more changes.
+    fn changed(&mut self) -> Result<Option<Scheme>>;
+}
+
+pub struct Portal {
+    proxy: Proxy<'static>,
+    signals: SignalIterator<'static>,
+}
+
+impl Portal {
+    pub fn new() -> Result<Portal> {
+        let connection = Connection::session()?;
+        let proxy = Proxy::new(&connection, DESTINATION, PATH, INTERFACE)?;
+        let signals =
+            proxy.receive_signal_with_args("SettingChanged", &[(0, NAMESPACE), (1, KEY)])?;
+
+        Ok(Portal { proxy, signals })
+    }
+}
+
+impl Source for Portal {
+    fn scheme(&mut self) -> Result<Scheme> {
+        // ReadOne was introduced in the second version of the interface, older
+        // portals only have Read which wraps the value in an extra variant.
+        let value: OwnedValue = match self.proxy.call("ReadOne", &(NAMESPACE, KEY)) {
+            Ok(value) => value,
+            Err(_) => self.proxy.call("Read", &(NAMESPACE, KEY))?,
+        };
+
+        to_scheme(&value)
+    }
+
+    fn changed(&mut self) -> Result<Option<Scheme>> {
+        for message in self.signals.by_ref() {
+            let (namespace, key, value): (String, String, OwnedValue) =
+                message.body().deserialize()?;
+
+            if namespace == NAMESPACE && key == KEY {
+                return to_scheme(&value).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn to_scheme(value: &Value) -> Result<Scheme> {
+    match value {
+        Value::Value(inner) => to_scheme(inner),
+        Value::U32(value) => Ok(Scheme::from(*value)),
+        _ => Err(anyhow!("Unexpected value for {NAMESPACE} {KEY}: {value:?}")),
+    }
+}
+
+// Reports the current color scheme right away and then every time it changes.
+pub fn monitor(mut source: impl Source + Send + 'static, sender: Sender<Event>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let result = (|| -> Result<()> {
+            sender.send(Event::SchemeChanged(source.scheme()?))?;
+            while let Some(scheme) = source.changed()? {
+                sender.send(Event::SchemeChanged(scheme))?;
+            }
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            eprintln!("Color scheme can't be followed: {err:?}");
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct StandIn {
+        current: Scheme,
+        changes: VecDeque<Scheme>,
+    }
+
+    impl Source for StandIn {
+        fn scheme(&mut self) -> Result<Scheme> {
+            Ok(self.current)
+        }
+
+        fn changed(&mut self) -> Result<Option<Scheme>> {
+            Ok(self.changes.pop_front())
+        }
+    }
+
+    #[test]
+    fn monitor_reports_current_scheme_then_changes() {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let source = StandIn {
+            current: Scheme::Light,
+            changes: VecDeque::from([Scheme::Dark, Scheme::NoPreference]),
+        };
+
+        monitor(source, sender).join().unwrap();
+
+        let schemes: Vec<Scheme> = receiver
+            .try_iter()
+            .map(|event| match event {
+                Event::SchemeChanged(scheme) => scheme,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+
+        assert_eq!(
+            schemes,
+            vec![Scheme::Light, Scheme::Dark, Scheme::NoPreference]
+        );
+    }
+
+    #[test]
+    fn value_is_unwrapped_from_nested_variants() {
+        let value = Value::Value(Box::new(Value::U32(1)));
+        assert_eq!(to_scheme(&value).unwrap(), Scheme::Dark);
+        assert_eq!(to_scheme(&Value::U32(2)).unwrap(), Scheme::Light);
+        assert_eq!(to_scheme(&Value::U32(0)).unwrap(), Scheme::NoPreference);
+        assert!(to_scheme(&Value::from("dark")).is_err());
+    }
+}
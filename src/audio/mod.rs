@@ -1,11 +1,13 @@
 use anyhow::{Result, anyhow};
-use crossbeam::channel::Sender;
+use crossbeam::channel::{self, Sender};
 use std::io::BufReader;
 use std::sync::{Arc, RwLock};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use std::{ffi::CString, os::unix::net::UnixStream};
 
 use crate::app::Event;
+use crate::metrics::Metrics;
 use pulseaudio::protocol::*;
 
 #[allow(dead_code)]
@@ -20,6 +22,10 @@ type SinkIndex = u32;
 type ProtocolVersion = u16;
 type ProtocolSequence = u32;
 
+// Sinks are queried at most once per interval, the events received in between
+// are gathered and every sink they point to is queried once.
+const QUERY_INTERVAL: Duration = Duration::from_millis(16);
+
 // Audio requires 2 connections to pulseaudio: One for receiving events coming from
 // the server, and one for getting information out of PulseAudio.
 impl Audio {
@@ -43,71 +49,206 @@ impl Audio {
         })
     }
 
-    pub fn monitor(&mut self, sender: Sender<Event>) -> JoinHandle<()> {
+    // Changes the volume of the default sink by delta, 1.0 being 100%. Every
+    // channel is scaled so the balance between them is kept.
+    pub fn adjust(&mut self, delta: f32) -> Result<()> {
+        write_command_message(
+            self.socket.get_mut(),
+            self.seq + 1,
+            &Command::GetServerInfo,
+            self.version,
+        )?;
+        let (seq, server) = read_reply_message::<ServerInfo>(&mut self.socket, self.version)?;
+        self.seq = seq;
+
+        let name = server
+            .default_sink_name
+            .ok_or(anyhow!("There's no default sink"))?;
+        let (index, cvolume) = {
+            let sinks = self.sinks.read().unwrap();
+            let sink = sinks
+                .iter()
+                .find(|sink| sink.name == name)
+                .ok_or(anyhow!("Sink couldn't be found for name: {name:?}"))?;
+            (sink.index, sink.cvolume.clone())
+        };
+
+        let channels: Vec<u32> = cvolume
+            .channels()
+            .iter()
+            .map(|volume| volume.as_u32())
+            .collect();
+        let mut volume = ChannelVolume::empty();
+        for channel in scale(&channels, delta) {
+            volume.push(Volume::from_u32_clamped(channel));
+        }
+
+        write_command_message(
+            self.socket.get_mut(),
+            self.seq + 1,
+            &Command::SetSinkVolume(SetDeviceVolumeParams {
+                device_index: Some(index),
+                device_name: None,
+                volume,
+            }),
+            self.version,
+        )?;
+        self.seq = read_ack_message(&mut self.socket)?;
+
+        Ok(())
+    }
+
+    pub fn monitor(&mut self, sender: Sender<Event>, metrics: Arc<Metrics>) -> JoinHandle<()> {
         let sinks = self.sinks.clone();
-        std::thread::spawn(|| Monitor::new(sinks, sender).unwrap().run().unwrap())
+        std::thread::spawn(|| Monitor::new(sinks, sender, metrics).unwrap().run().unwrap())
     }
 }
 
 struct Monitor {
     version: ProtocolVersion,
-    sequence: ProtocolSequence,
     socket: BufReader<UnixStream>,
     sinks: Arc<RwLock<Vec<SinkInfo>>>,
     sender: Sender<Event>,
+    metrics: Arc<Metrics>,
+    // Name of the default sink, the device in use was switched when it changes.
+    default: Option<CString>,
+    queried: Option<Instant>,
+}
+
+// What the server reported a change of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Notice {
+    Sink(SinkIndex),
+    // The sink was unplugged or unloaded.
+    Removed(SinkIndex),
+    // The server itself changed, e.g. another sink became the default one.
+    Server,
 }
 
 impl Monitor {
-    fn new(sinks: Arc<RwLock<Vec<SinkInfo>>>, sender: Sender<Event>) -> Result<Self> {
-        let (socket, version, seq) = initialize_with_client_name("shield-monitor")?;
-        Ok(Monitor {
+    fn new(
+        sinks: Arc<RwLock<Vec<SinkInfo>>>,
+        sender: Sender<Event>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let (socket, version, _) = initialize_with_client_name("shield-monitor")?;
+        let mut monitor = Monitor {
             socket,
             version,
             sinks,
             sender,
-            sequence: seq,
-        })
+            metrics,
+            default: None,
+            queried: None,
+        };
+        monitor.default = monitor.default_sink()?;
+        Ok(monitor)
     }
 
+    // Events are read by their own connection so the sinks can be queried on
+    // this one without the replies getting mixed with the events.
     fn run(&mut self) -> Result<()> {
+        let (notices_tx, notices) = channel::unbounded();
+        let metrics = self.metrics.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = listen(notices_tx, metrics) {
+                eprintln!("Sink events stopped: {err:?}");
+            }
+        });
+
+        loop {
+            let mut dirty = vec![notices.recv()?];
+            let until = self
+                .queried
+                .map_or_else(Instant::now, |queried| queried + QUERY_INTERVAL);
+            while let Ok(notice) = notices.recv_deadline(until) {
+                // A sink removed in the meantime can't be queried anymore.
+                if let Notice::Removed(index) = notice {
+                    dirty.retain(|dirty| *dirty != Notice::Sink(index));
+                }
+                if !dirty.contains(&notice) {
+                    dirty.push(notice);
+                }
+            }
+
+            // The sinks are brought up to date before the default one is
+            // looked up, so a switch shows the level of the new sink.
+            dirty.sort_by_key(|notice| *notice == Notice::Server);
+            for notice in dirty {
+                match notice {
+                    Notice::Sink(index) => self.query(index)?,
+                    Notice::Removed(index) => self
+                        .sinks
+                        .write()
+                        .unwrap()
+                        .retain(|sink| sink.index != index),
+                    Notice::Server => self.server_changed()?,
+                }
+            }
+            self.queried = Some(Instant::now());
+        }
+    }
+
+    fn default_sink(&mut self) -> Result<Option<CString>> {
         write_command_message(
             self.socket.get_mut(),
-            self.sequence + 1,
-            &Command::Subscribe(SubscriptionMask::SINK),
+            1,
+            &Command::GetServerInfo,
             self.version,
         )?;
+        let (_seq, server) = read_reply_message::<ServerInfo>(&mut self.socket, self.version)?;
 
-        self.sequence = read_ack_message(&mut self.socket)?;
+        Ok(server.default_sink_name)
+    }
 
-        loop {
-            let (_seq, event) = read_command_message(&mut self.socket, self.version)?;
-            match event {
-                Command::SubscribeEvent(event) => {
-                    if let Some(index) = event.index {
-                        let change = match self.switch(index) {
-                            Ok(change) => change,
-                            Err(err) => {
-                                eprintln!("Error occured for index: #{index:?}: #{err:?}");
-                                continue;
-                            }
-                        };
-
-                        if change.volume_changed() {
-                            self.sender.send(Event::VolumeChanged(change.volume()));
-                        }
-                    }
-                }
-                _ => eprintln!("got unexpected event {:?}", event),
+    // Only a change of the default sink counts as a change of device, the
+    // volume of the sinks in the background doesn't.
+    fn server_changed(&mut self) -> Result<()> {
+        let default = self.default_sink()?;
+        if default == self.default {
+            return Ok(());
+        }
+        self.default = default;
+
+        let shown = {
+            let sinks = self.sinks.read().unwrap();
+            sinks
+                .iter()
+                .find(|sink| Some(&sink.name) == self.default.as_ref())
+                .map(level)
+        };
+        match shown {
+            Some(level) => self.sender.send(Event::DeviceChanged(level))?,
+            None => eprintln!("Default sink couldn't be found: {:?}", self.default),
+        }
+
+        Ok(())
+    }
+
+    fn query(&mut self, index: SinkIndex) -> Result<()> {
+        self.metrics.sink_queried();
+        let change = match self.switch(index) {
+            Ok(Some(change)) => change,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                eprintln!("Error occured for index: #{index:?}: #{err:?}");
+                return Ok(());
             }
+        };
+
+        if change.mute_changed() {
+            self.sender.send(Event::MuteChanged(level(&change.new)))?;
+        } else if change.volume_changed() {
+            self.sender.send(Event::VolumeChanged(change.volume()))?;
         }
+
+        Ok(())
     }
 
     // The SinkInfo is outdated and needs to be replaced with the current
     // state. Once done, an event should be emitted so that the UI can be
     // updated.
-    fn switch(&mut self, index: SinkIndex) -> Result<Change> {
-        let mut change: Option<Change> = None;
-
+    fn switch(&mut self, index: SinkIndex) -> Result<Option<Change>> {
         write_command_message(
             self.socket.get_mut(),
             1,
@@ -119,20 +260,58 @@ impl Monitor {
         )?;
 
         let (_seq, new_sink) = read_reply_message::<SinkInfo>(&mut self.socket, self.version)?;
-        let mut sinks = self.sinks.write().unwrap();
-
-        for sink_info in sinks.iter_mut() {
-            if sink_info.index == new_sink.index {
-                change = Some(Change {
-                    old: sink_info.clone(),
-                    new: new_sink.clone(),
-                });
-                *sink_info = new_sink;
-                break;
-            }
+        Ok(replace(&mut self.sinks.write().unwrap(), new_sink))
+    }
+}
+
+// Replaces the sink with the same index. A sink that isn't known yet, e.g. a
+// headset plugged in after the start, is added, there's no change to show for
+// it until it changes again.
+fn replace(sinks: &mut Vec<SinkInfo>, new_sink: SinkInfo) -> Option<Change> {
+    match sinks.iter_mut().find(|sink| sink.index == new_sink.index) {
+        Some(sink) => Some(Change {
+            old: std::mem::replace(sink, new_sink.clone()),
+            new: new_sink,
+        }),
+        None => {
+            sinks.push(new_sink);
+            None
         }
+    }
+}
+
+// Subscribes to the changes of the sinks and of the server, and sends the
+// index of every sink that changed.
+fn listen(sender: Sender<Notice>, metrics: Arc<Metrics>) -> Result<()> {
+    let (mut socket, version, seq) = initialize_with_client_name("shield-events")?;
+    write_command_message(
+        socket.get_mut(),
+        seq + 1,
+        &Command::Subscribe(SubscriptionMask::SINK | SubscriptionMask::SERVER),
+        version,
+    )?;
+    read_ack_message(&mut socket)?;
 
-        change.ok_or(anyhow!("Sink couldn't be found for index: {index:?}"))
+    loop {
+        let (_seq, event) = read_command_message(&mut socket, version)?;
+        match event {
+            Command::SubscribeEvent(event) => {
+                metrics.event_received();
+                match (event.event_facility, event.event_type, event.index) {
+                    (SubscriptionEventFacility::Server, _, _) => sender.send(Notice::Server)?,
+                    (
+                        SubscriptionEventFacility::Sink,
+                        SubscriptionEventType::Removed,
+                        Some(index),
+                    ) => sender.send(Notice::Removed(index))?,
+                    (SubscriptionEventFacility::Sink, _, Some(index)) => {
+                        sender.send(Notice::Sink(index))?
+                    }
+                    _ => {}
+                }
+            }
+            _ => eprintln!("got unexpected event {:?}", event),
+        }
     }
 }
 
@@ -172,6 +351,26 @@ fn initialize_with_client_name(
     Ok((sock, protocol_version, seq))
 }
 
+// Moves the loudest channel by delta and the others in proportion, like
+// `pactl set-sink-volume` with a relative value. Scrolling doesn't raise the
+// volume past 100%, but a sink already amplified past it keeps its volume.
+fn scale(channels: &[u32], delta: f32) -> Vec<u32> {
+    let norm = 0x10000 as f32;
+    let loudest = channels.iter().copied().max().unwrap_or_default() as f32 / norm;
+    let target = (loudest + delta).clamp(0.0, loudest.max(1.0));
+
+    channels
+        .iter()
+        .map(|channel| {
+            if loudest == 0.0 {
+                (target * norm) as u32
+            } else {
+                (*channel as f32 * target / loudest).round() as u32
+            }
+        })
+        .collect()
+}
+
 struct Change {
     old: SinkInfo,
     new: SinkInfo,
@@ -182,8 +381,73 @@ impl Change {
         self.old.cvolume.channels().first().unwrap() != self.new.cvolume.channels().first().unwrap()
     }
 
+    pub fn mute_changed(&self) -> bool {
+        self.old.mute != self.new.mute
+    }
+
     pub fn volume(&self) -> f32 {
-        let volume = self.new.cvolume.channels().first().unwrap().as_u32();
-        volume as f32 / 0x10000 as f32
+        volume(&self.new)
+    }
+}
+
+fn volume(sink: &SinkInfo) -> f32 {
+    let volume = sink.cvolume.channels().first().unwrap().as_u32();
+    volume as f32 / 0x10000 as f32
+}
+
+// The level shown by the shield, a muted sink is at 0.
+fn level(sink: &SinkInfo) -> f32 {
+    if sink.mute { 0.0 } else { volume(sink) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NORM: u32 = 0x10000;
+
+    #[test]
+    fn scaling_keeps_the_balance_of_the_channels() {
+        assert_eq!(
+            scale(&[NORM / 2, NORM / 4], 0.25),
+            vec![NORM * 3 / 4, NORM * 3 / 8]
+        );
+        assert_eq!(scale(&[NORM / 2, NORM / 4], -1.0), vec![0, 0]);
+    }
+
+    #[test]
+    fn silent_channels_are_raised_together() {
+        assert_eq!(scale(&[0, 0], 0.5), vec![NORM / 2, NORM / 2]);
+    }
+
+    fn sink(index: SinkIndex, mute: bool) -> SinkInfo {
+        SinkInfo {
+            index,
+            name: CString::new(format!("sink-{index}")).unwrap(),
+            mute,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sinks_added_after_the_start_are_kept() {
+        let mut sinks = vec![sink(1, false)];
+
+        assert!(replace(&mut sinks, sink(2, false)).is_none());
+        assert_eq!(sinks.len(), 2);
+
+        let change = replace(&mut sinks, sink(2, true)).unwrap();
+        assert!(change.mute_changed());
+        assert_eq!(sinks.len(), 2);
+        assert!(sinks[1].mute);
+    }
+
+    #[test]
+    fn amplified_volume_is_kept_but_not_raised() {
+        let boosted = NORM * 3 / 2;
+
+        assert_eq!(scale(&[NORM], 0.1), vec![NORM]);
+        assert_eq!(scale(&[boosted], 0.1), vec![boosted]);
+        assert_eq!(scale(&[boosted], -0.5), vec![NORM]);
     }
 }
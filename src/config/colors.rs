@@ -0,0 +1,105 @@
+use config::{Config, Map, Value, ValueKind};
+use vello::peniko::Color;
+use vello::peniko::color::{AlphaColor, Srgb, parse_color};
+
+// Colors are written either as an RGB(A) array of integers between 0 and 255,
+// or as a string the CSS way: hex (#2a2849e6), names (rebeccapurple), rgba()
+// and hsl() functions. A string can also name an entry of the [palette] table,
+// the entries of the palette take precedence over the CSS names.
+pub fn parse(value: &ValueKind, palette: &Map<String, Value>) -> Result<AlphaColor<Srgb>, String> {
+    match value {
+        ValueKind::String(name) if palette.contains_key(name) => literal(&palette[name].kind)
+            .map_err(|err| format!("refers to palette.{name}, which {err}")),
+        value => literal(value),
+    }
+}
+
+// Entries of the palette are colors written out, they can't refer to each other.
+pub fn palette(config: &Config) -> Map<String, Value> {
+    config.get_table("palette").unwrap_or_default()
+}
+
+pub fn literal(value: &ValueKind) -> Result<AlphaColor<Srgb>, String> {
+    match value {
+        ValueKind::Array(components) => {
+            let components: Option<Vec<u8>> = components
+                .iter()
+                .map(|component| component.clone().into_uint().ok())
+                .map(|component| component.and_then(|component| u8::try_from(component).ok()))
+                .collect();
+
+            match components.as_deref() {
+                Some([r, g, b]) => Ok(Color::from_rgb8(*r, *g, *b)),
+                Some([r, g, b, a]) => Ok(Color::from_rgba8(*r, *g, *b, *a)),
+                _ => Err("should be an array of 3 or 4 integers between 0 and 255".to_string()),
+            }
+        }
+        ValueKind::String(text) => parse_color(text)
+            .map(|color| color.to_alpha_color::<Srgb>())
+            .map_err(|err| {
+                format!("isn't a hex color, a CSS color or an entry of the palette ({err})")
+            }),
+        _ => Err("should be a color, either a string or an array of integers".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::toml_config;
+
+    fn color(toml: &str) -> Result<AlphaColor<Srgb>, String> {
+        let config = toml_config(toml).unwrap();
+        let value = config.get::<Value>("color").unwrap();
+
+        parse(&value.kind, &palette(&config))
+    }
+
+    #[test]
+    fn every_syntax_gives_the_same_color() {
+        let expected = Color::from_rgba8(42, 40, 73, 230).to_rgba8();
+
+        for syntax in [
+            "color = [42, 40, 73, 230]",
+            "color = \"#2a2849e6\"",
+            "color = \"rgba(42, 40, 73, 0.902)\"",
+            "color = \"accent\"\n[palette]\naccent = \"#2a2849e6\"",
+        ] {
+            assert_eq!(color(syntax).unwrap().to_rgba8(), expected, "{syntax}");
+        }
+    }
+
+    #[test]
+    fn names_and_hsl_are_understood() {
+        assert_eq!(
+            color("color = \"red\"").unwrap().to_rgba8(),
+            Color::from_rgb8(255, 0, 0).to_rgba8()
+        );
+        assert_eq!(
+            color("color = \"hsl(120, 100%, 50%)\"").unwrap().to_rgba8(),
+            Color::from_rgb8(0, 255, 0).to_rgba8()
+        );
+    }
+
+    #[test]
+    fn palette_entries_shadow_css_names() {
+        let color = color("color = \"red\"\n[palette]\nred = [200, 10, 10]").unwrap();
+
+        assert_eq!(color.to_rgba8(), Color::from_rgb8(200, 10, 10).to_rgba8());
+    }
+
+    #[test]
+    fn invalid_colors_are_explained() {
+        assert!(
+            color("color = \"#12345\"")
+                .unwrap_err()
+                .starts_with("isn't a hex color")
+        );
+        assert!(
+            color("color = \"accent\"\n[palette]\naccent = \"nope\"")
+                .unwrap_err()
+                .starts_with("refers to palette.accent")
+        );
+        assert!(color("color = 12").is_err());
+    }
+}
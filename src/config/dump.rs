@@ -0,0 +1,248 @@
+use serde_json::{Map, Value, json};
+
+use super::Profile;
+use super::schema::{FIELDS, Field, GLOBAL, Kind, Literal};
+
+// JSON Schema of the configuration, the sections are nested objects like in
+// the TOML files. Unknown keys are refused, the same way the validation does.
+pub fn json_schema() -> String {
+    let mut root = object();
+    for field in FIELDS {
+        let mut table = &mut root;
+        let mut path: Vec<&str> = field.key.split('.').collect();
+        let name = path.pop().unwrap_or_default();
+
+        for section in path {
+            let properties = table["properties"].as_object_mut().unwrap();
+            table = properties.entry(section).or_insert_with(object);
+        }
+        table["properties"][name] = property(field);
+    }
+
+    let mut sections = root["properties"].clone();
+    for key in GLOBAL {
+        sections.as_object_mut().unwrap().remove(key);
+    }
+    let mut profiles = object();
+    profiles["description"] = json!(PROFILES.replace('\n', " "));
+    for profile in Profile::ALL {
+        let mut table = object();
+        table["properties"] = sections.clone();
+        profiles["properties"][profile.name()] = table;
+    }
+    root["properties"]["profile"] = profiles;
+
+    root["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+    root["title"] = json!("Shield configuration");
+    serde_json::to_string_pretty(&root).unwrap()
+}
+
+const PROFILES: &str = "Profiles override the keys above for one kind of event: volume, mute,\n\
+     microphone or device. Every key can be overridden but theme, renderer, output\n\
+     and palette.";
+
+fn object() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {},
+    })
+}
+
+fn property(field: &Field) -> Value {
+    let mut property = match &field.kind {
+        Kind::Bool => json!({ "type": "boolean" }),
+        Kind::Integer { min, max } => {
+            bounded("integer", min.map(Value::from), max.map(Value::from))
+        }
+        Kind::Float { min, max } => bounded("number", min.map(Value::from), max.map(Value::from)),
+        Kind::String => json!({ "type": "string" }),
+        Kind::Choice(choices) => json!({ "type": "string", "enum": choices }),
+        Kind::Color => color(),
+        Kind::Palette => json!({ "type": "object", "additionalProperties": color() }),
+    };
+
+    property["description"] = json!(field.doc.replace('\n', " "));
+    match &field.default {
+        Literal::Bool(value) => property["default"] = json!(value),
+        Literal::Integer(value) => property["default"] = json!(value),
+        Literal::Float(value) => property["default"] = json!(value),
+        Literal::String(value) => property["default"] = json!(value),
+        Literal::Color(value) => property["default"] = json!(value),
+        Literal::Example(_) => {}
+    }
+    property
+}
+
+fn bounded(kind: &str, min: Option<Value>, max: Option<Value>) -> Value {
+    let mut property = Map::new();
+    property.insert("type".to_string(), json!(kind));
+    if let Some(min) = min {
+        property.insert("minimum".to_string(), min);
+    }
+    if let Some(max) = max {
+        property.insert("maximum".to_string(), max);
+    }
+    Value::Object(property)
+}
+
+fn color() -> Value {
+    json!({
+        "oneOf": [
+            {
+                "type": "array",
+                "items": { "type": "integer", "minimum": 0, "maximum": 255 },
+                "minItems": 3,
+                "maxItems": 4,
+            },
+            {
+                "type": "string",
+                "description": "Hex color, CSS color name or function, or name of a palette entry",
+            },
+        ],
+    })
+}
+
+// The default configuration in TOML with every line commented out, so the
+// file can be used as a starting point and only what's uncommented applies.
+// Keys without a default show an example instead.
+pub fn default_toml() -> String {
+    let mut toml = String::new();
+    let mut current = "";
+
+    for field in FIELDS {
+        let (section, name) = match &field.kind {
+            // Written as an inline table among the top level keys.
+            Kind::Palette => ("", field.key),
+            _ => field.key.rsplit_once('.').unwrap_or(("", field.key)),
+        };
+
+        if section != current {
+            toml.push_str(&format!("\n# [{section}]\n"));
+            current = section;
+        }
+
+        toml.push('\n');
+        for line in field.doc.lines() {
+            toml.push_str(&format!("# {line}\n"));
+        }
+        toml.push_str(&format!("# {name} = {}\n", literal(&field.default)));
+    }
+
+    toml.push('\n');
+    for line in PROFILES.lines() {
+        toml.push_str(&format!("# {line}\n"));
+    }
+    toml.push_str("# [profile.mute.color]\n# background = [122, 31, 31, 230]\n");
+
+    toml.trim_start().to_string()
+}
+
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Bool(value) => value.to_string(),
+        Literal::Integer(value) => value.to_string(),
+        Literal::Float(value) => format!("{value:?}"),
+        Literal::String(value) => format!("{value:?}"),
+        Literal::Color([r, g, b, a]) => format!("[{r}, {g}, {b}, {a}]"),
+        Literal::Example(example) => example.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::config::schema::validate;
+    use crate::config::toml_config;
+
+    // Sections that are off unless they're present, setting any of their
+    // keys turns them on.
+    const OPTIONAL: [&str; 4] = ["border.", "shadow.", "glow.", "color.gradient."];
+
+    fn uncomment(toml: &str) -> String {
+        toml.lines()
+            .filter(|line| line.starts_with("# [") || line.contains(" = "))
+            .map(|line| line.trim_start_matches("# "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn uncommented_default_configuration_is_valid() {
+        let toml = uncomment(&default_toml());
+        let config = toml_config(&toml).unwrap();
+
+        assert_eq!(validate(&config), vec![], "{toml}");
+    }
+
+    // Keys are written dotted at the root, so keys of the same section can
+    // be put in one document.
+    fn document<'a>(fields: impl IntoIterator<Item = &'a Field>) -> String {
+        fields
+            .into_iter()
+            .map(|field| format!("{} = {}\n", field.key, literal(&field.default)))
+            .collect()
+    }
+
+    #[test]
+    fn defaults_match_the_settings() {
+        let defaults = format!("{:?}", Settings::default());
+
+        for field in FIELDS {
+            if matches!(field.default, Literal::Example(_))
+                || OPTIONAL
+                    .iter()
+                    .any(|section| field.key.starts_with(section))
+            {
+                continue;
+            }
+
+            let settings = Settings::from_toml(&document([field])).unwrap();
+            assert_eq!(format!("{settings:?}"), defaults, "{}", field.key);
+        }
+    }
+
+    // An optional section is turned on with every key set, then with a single
+    // key set and the ones that have an example, the missing keys must fall
+    // back on their documented default.
+    #[test]
+    fn defaults_match_the_optional_sections() {
+        for section in OPTIONAL {
+            let fields: Vec<&Field> = FIELDS
+                .iter()
+                .filter(|field| field.key.starts_with(section))
+                .collect();
+            let (examples, keys): (Vec<&Field>, Vec<&Field>) = fields
+                .iter()
+                .copied()
+                .partition(|field| matches!(field.default, Literal::Example(_)));
+
+            let everything = Settings::from_toml(&document(fields.iter().copied())).unwrap();
+            let everything = format!("{everything:?}");
+            assert_ne!(
+                everything,
+                format!("{:?}", Settings::default()),
+                "{section}"
+            );
+
+            for field in keys {
+                let toml = document(examples.iter().copied().chain([field]));
+                let settings = Settings::from_toml(&toml).unwrap();
+
+                assert_eq!(format!("{settings:?}"), everything, "{}", field.key);
+            }
+        }
+    }
+
+    #[test]
+    fn schema_nests_the_sections() {
+        let schema: Value = serde_json::from_str(&json_schema()).unwrap();
+        let width = &schema["properties"]["frame"]["properties"]["size"]["properties"]["width"];
+
+        assert_eq!(width["type"], "integer");
+        assert_eq!(width["minimum"], 100);
+        assert_eq!(width["maximum"], 800);
+        assert_eq!(width["default"], 300);
+    }
+}
@@ -0,0 +1,731 @@
+use anyhow::{Result, bail};
+use config::*;
+use derive_getters::Getters;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use vello::peniko::Color;
+use vello::peniko::color::{AlphaColor, Srgb};
+
+use crate::appearance::Scheme;
+
+mod colors;
+mod dump;
+mod paths;
+mod schema;
+mod theme;
+mod watch;
+pub use dump::{default_toml, json_schema};
+pub use schema::Problem;
+pub use watch::{Watcher, watch};
+
+#[derive(Getters, Debug, Clone)]
+pub struct Settings {
+    size: (u32, u32),
+    position: (i32, i32),
+    anchor: Anchor,
+    layer: Stacking,
+    radius: f64,
+    background_color: AlphaColor<Srgb>,
+    foreground_color: AlphaColor<Srgb>,
+    gradient: Option<Gradient>,
+    border: Option<Border>,
+    shadow: Option<Shadow>,
+    glow: Option<Glow>,
+    text: Text,
+    bar: Bar,
+    transition: Duration,
+    light: Option<Palette>,
+    dark: Option<Palette>,
+    follow_system: bool,
+    renderer: Renderer,
+    output: Output,
+    behavior: Behavior,
+    // File given on the command line, kept so the settings can be reloaded.
+    path: Option<String>,
+    // Files the settings were read from, in the order they were loaded.
+    files: Vec<PathBuf>,
+    // File of the theme, unless it is a preset.
+    theme: Option<PathBuf>,
+    // Values of the configuration that were ignored or brought back in range.
+    problems: Vec<Problem>,
+    // Settings of the profiles present in the configuration.
+    profiles: Vec<(Profile, Arc<Settings>)>,
+}
+
+// The position of the shield is relative to the edges of the anchor. When the
+// anchor is centered horizontally, x is kept as a margin from the right edge and
+// the shield is centered in the rest of the output. When it's centered
+// vertically, the shield is centered and y is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Start,
+    Center,
+    End,
+}
+
+impl Anchor {
+    // Edges the shield is anchored to, horizontally then vertically.
+    pub fn edges(self) -> (Edge, Edge) {
+        match self {
+            Anchor::TopLeft => (Edge::Start, Edge::Start),
+            Anchor::Top => (Edge::Center, Edge::Start),
+            Anchor::TopRight => (Edge::End, Edge::Start),
+            Anchor::Left => (Edge::Start, Edge::Center),
+            Anchor::Center => (Edge::Center, Edge::Center),
+            Anchor::Right => (Edge::End, Edge::Center),
+            Anchor::BottomLeft => (Edge::Start, Edge::End),
+            Anchor::Bottom => (Edge::Center, Edge::End),
+            Anchor::BottomRight => (Edge::End, Edge::End),
+        }
+    }
+}
+
+// Layer of the layer shell the shield is shown on. Overlay is above fullscreen
+// windows, Top is below them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    Top,
+    Overlay,
+}
+
+// Focused lets the compositor choose the output, which usually is the one with
+// the keyboard focus. Named matches the name of the output (e.g. DP-1) or part
+// of its description.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Focused,
+    All,
+    Named(String),
+}
+
+// Kind of event the shield shows. Each kind can have a [profile.<name>] section
+// overriding any key of the configuration but theme, renderer, output and palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Volume,
+    Mute,
+    // No audio source is monitored, the level of a microphone only comes from
+    // notifications.
+    Microphone,
+    Device,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 4] = [
+        Profile::Volume,
+        Profile::Mute,
+        Profile::Microphone,
+        Profile::Device,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Profile::Volume => "volume",
+            Profile::Mute => "mute",
+            Profile::Microphone => "microphone",
+            Profile::Device => "device",
+        }
+    }
+}
+
+// Auto uses the GPU when an adapter can present to the surface and falls back
+// to rasterizing on the CPU otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    Auto,
+    Gpu,
+    Cpu,
+}
+
+// Colors used in place of the background and foreground colors when the
+// matching color scheme is active.
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Palette {
+    background: AlphaColor<Srgb>,
+    foreground: AlphaColor<Srgb>,
+}
+
+// When set, the gradient replaces the background color of the shield.
+#[derive(Debug, Clone, Copy)]
+pub enum Gradient {
+    // The angle is in degrees, 0 goes from left to right and 90 from top to bottom.
+    Linear {
+        from: AlphaColor<Srgb>,
+        to: AlphaColor<Srgb>,
+        angle: f64,
+    },
+    // Goes from the center of the shield to its edges.
+    Radial {
+        from: AlphaColor<Srgb>,
+        to: AlphaColor<Srgb>,
+    },
+}
+
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Border {
+    width: f64,
+    color: AlphaColor<Srgb>,
+}
+
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Shadow {
+    color: AlphaColor<Srgb>,
+    blur: f64,
+    offset: (f64, f64),
+}
+
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Glow {
+    width: f64,
+    color: AlphaColor<Srgb>,
+}
+
+// How long the shield stays on screen and how it reacts to the pointer. The
+// event timeout applies when a sink is muted or another sink becomes the
+// default one.
+// When pinned, every change restarts the countdown so the shield stays while the
+// value keeps changing; otherwise it hides once the timeout elapsed since it
+// appeared. Without auto_hide, the shield never hides on its own.
+// When interactive, clicking the shield hides it and scrolling over it changes
+// the volume. Otherwise, the pointer goes through it.
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Behavior {
+    timeout: Duration,
+    event_timeout: Duration,
+    pin: bool,
+    auto_hide: bool,
+    interactive: bool,
+}
+
+// The offset is the vertical distance between the top of the shield and the text.
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Text {
+    visible: bool,
+    size: f32,
+    offset: f32,
+}
+
+// The bar sits at the bottom of the shield, the margin is the space left
+// between the bar and the edges of the shield. The track is the part of the
+// bar that isn't filled.
+#[derive(Getters, Debug, Clone, Copy)]
+pub struct Bar {
+    visible: bool,
+    height: f64,
+    margin: f64,
+    color: AlphaColor<Srgb>,
+    track: AlphaColor<Srgb>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            size: (300, 200),
+            position: (0, 250),
+            anchor: Anchor::Bottom,
+            layer: Stacking::Top,
+            radius: 14.0,
+            background_color: Color::from_rgba8(42, 40, 68, 220),
+            foreground_color: Color::from_rgba8(255, 255, 255, 150),
+            gradient: None,
+            border: None,
+            shadow: None,
+            glow: None,
+            text: Text {
+                visible: true,
+                size: 112.0,
+                offset: 85.0,
+            },
+            bar: Bar {
+                visible: false,
+                height: 8.0,
+                margin: 24.0,
+                color: Color::from_rgba8(255, 255, 255, 150),
+                track: Color::from_rgba8(255, 255, 255, 40),
+            },
+            transition: Duration::from_millis(150),
+            light: None,
+            dark: None,
+            follow_system: false,
+            renderer: Renderer::Auto,
+            output: Output::Focused,
+            behavior: Behavior {
+                timeout: Duration::from_millis(750),
+                event_timeout: Duration::from_millis(1500),
+                pin: true,
+                auto_hide: true,
+                interactive: false,
+            },
+            path: None,
+            files: vec![],
+            theme: None,
+            problems: vec![],
+            profiles: vec![],
+        }
+    }
+}
+
+impl Settings {
+    pub fn new(path: Option<String>) -> Result<Settings> {
+        let mut builder = Config::builder();
+        let mut files = vec![];
+
+        for source in paths::sources(path.as_deref()) {
+            match paths::resolve(&source.base) {
+                Some(file) => {
+                    builder = builder.add_source(File::from(file.clone()));
+                    files.push(file);
+                }
+                None if source.required => {
+                    bail!("Configuration file {:?} can't be found", source.base)
+                }
+                None => {}
+            }
+        }
+
+        let mut settings = Self::from_config(builder.build()?)?;
+        settings.path = path;
+        settings.files = files;
+        Ok(settings)
+    }
+
+    // Reads the configuration files again, the current settings are left as is.
+    pub fn reload(&self) -> Result<Settings> {
+        Self::new(self.path.clone())
+    }
+
+    // Files the settings can be read from, whether they exist or not, so
+    // creating one is noticed too. The names may come without their extension.
+    // The theme in use is one of them, editing it reloads the settings as well.
+    pub fn sources(&self) -> Vec<PathBuf> {
+        paths::sources(self.path.as_deref())
+            .into_iter()
+            .map(|source| source.base)
+            .chain(self.theme.clone())
+            .collect()
+    }
+
+    // Tells which files the settings were read from, printed in verbose mode.
+    pub fn loaded(&self) -> String {
+        if self.files.is_empty() {
+            return "No configuration file found, the defaults are used".to_string();
+        }
+
+        let files: Vec<String> = self.files.iter().map(|file| format!("{file:?}")).collect();
+        format!("Configuration loaded from {}", files.join(", "))
+    }
+
+    // Problems are printed and the settings are used regardless, unless strict
+    // mode asks to refuse a configuration that has any.
+    pub fn report(&self, strict: bool) -> Result<()> {
+        for problem in &self.problems {
+            eprintln!("{problem}");
+        }
+
+        if strict && !self.problems.is_empty() {
+            bail!(
+                "The configuration has {} problem(s), strict mode refuses it",
+                self.problems.len()
+            );
+        }
+        Ok(())
+    }
+
+    // Builds the settings out of a TOML document instead of the configuration files.
+    #[cfg(test)]
+    pub fn from_toml(content: &str) -> Result<Settings> {
+        Self::from_config(toml_config(content)?)
+    }
+
+    fn from_config(mut config: Config) -> Result<Settings> {
+        let mut problems = vec![];
+        let mut theme_path = None;
+
+        // The theme goes underneath the configuration so every key set in the
+        // configuration overrides the one from the theme. Its file is watched
+        // even when it can't be loaded, so fixing it is noticed.
+        if let Ok(name) = config.get_string("theme") {
+            theme_path = theme::path(&name);
+            match theme::source(&name) {
+                Ok(theme) => {
+                    config = Config::builder()
+                        .add_source(theme)
+                        .add_source(config)
+                        .build()?;
+                }
+                Err(err) => problems.push(Problem {
+                    origin: None,
+                    key: "theme".to_string(),
+                    value: format!("{name:?}"),
+                    message: format!("can't be used ({err})"),
+                }),
+            }
+        }
+        problems.extend(schema::validate(&config));
+
+        let mut settings = Self::read(&config);
+        settings.problems = problems;
+        settings.theme = theme_path;
+
+        // A profile is the configuration with the keys of its section on top.
+        for profile in Profile::ALL {
+            let Ok(table) = config.get_table(&format!("profile.{}", profile.name())) else {
+                continue;
+            };
+
+            // Keys that apply to the whole shield are reported by the schema
+            // and left out.
+            let mut builder = Config::builder().add_source(config.clone());
+            for (key, value) in leaves(String::new(), table) {
+                let section = key.split('.').next().unwrap_or_default();
+                if !schema::GLOBAL.contains(&section) {
+                    builder = builder.set_override(key, value)?;
+                }
+            }
+            let profiled = Self::read(&builder.build()?);
+            settings.profiles.push((profile, Arc::new(profiled)));
+        }
+
+        Ok(settings)
+    }
+
+    // Reads every setting out of the configuration, the ones that aren't set
+    // keep their default.
+    fn read(config: &Config) -> Settings {
+        let mut settings = Self::default();
+
+        set_size(&mut settings.size, config);
+        set_position(&mut settings.position, config);
+        set_anchor(&mut settings.anchor, config);
+        set_stacking(&mut settings.layer, config);
+        set_radius(&mut settings.radius, config);
+        set_color(&mut settings.background_color, config, "color.background");
+        set_color(&mut settings.foreground_color, config, "color.foreground");
+        set_gradient(&mut settings.gradient, config, settings.background_color);
+        set_border(&mut settings.border, config);
+        set_shadow(&mut settings.shadow, config);
+        set_glow(&mut settings.glow, config);
+        set_text(&mut settings.text, config);
+        set_bar(&mut settings.bar, config);
+        set_transition(&mut settings.transition, config);
+        set_renderer(&mut settings.renderer, config);
+        set_output(&mut settings.output, config);
+        set_behavior(&mut settings.behavior, config);
+        set_palette(&mut settings.light, config, "color.light", &settings);
+        set_palette(&mut settings.dark, config, "color.dark", &settings);
+
+        // The system color scheme is only followed when there is a palette to switch to.
+        let palettes = settings.light.is_some() || settings.dark.is_some();
+        match config.get_string("color.scheme").as_deref() {
+            Ok("light") => settings = settings.with_scheme(Scheme::Light),
+            Ok("dark") => settings = settings.with_scheme(Scheme::Dark),
+            Ok("system") | Err(_) => settings.follow_system = palettes,
+            // Reported by the validation, like every other invalid value.
+            Ok(_) => {}
+        }
+
+        settings
+    }
+
+    // Settings to show an event of the kind with, the settings themselves when
+    // the kind has no profile.
+    pub fn resolve(self: &Arc<Self>, profile: Profile) -> Arc<Settings> {
+        self.profiles
+            .iter()
+            .find(|(kind, _)| *kind == profile)
+            .map_or_else(|| self.clone(), |(_, settings)| settings.clone())
+    }
+
+    // Returns the settings with the colors of the palette matching the scheme.
+    // Without a palette for the scheme, the configured colors are kept.
+    pub fn with_scheme(&self, scheme: Scheme) -> Settings {
+        let mut settings = self.clone();
+        let palette = match scheme {
+            Scheme::Light => self.light,
+            Scheme::Dark => self.dark,
+            Scheme::NoPreference => None,
+        };
+
+        if let Some(palette) = palette {
+            settings.background_color = palette.background;
+            settings.foreground_color = palette.foreground;
+        }
+
+        settings
+    }
+
+    // Space needed around the shield so its shadow isn't clipped by the
+    // edges of the surface.
+    pub fn padding(&self) -> f64 {
+        self.shadow.map_or(0.0, |shadow| {
+            let offset = shadow.offset.0.abs().max(shadow.offset.1.abs());
+            (shadow.blur * 3.0 + offset).ceil()
+        })
+    }
+
+    // Size of the surface the shield is drawn on, which includes the padding.
+    pub fn surface_size(&self) -> (u32, u32) {
+        let padding = (self.padding() * 2.0) as u32;
+        (self.size.0 + padding, self.size.1 + padding)
+    }
+}
+
+// The configuration of a TOML document, read like the configuration files are.
+// The tests build their configurations with it.
+#[cfg(test)]
+pub fn toml_config(content: &str) -> Result<Config> {
+    Ok(Config::builder()
+        .add_source(File::from_str(content, FileFormat::Toml))
+        .build()?)
+}
+// Flattens a table into the keys of its values, the arrays being values.
+fn leaves(prefix: String, table: Map<String, Value>) -> Vec<(String, Value)> {
+    let mut flat = vec![];
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+        match value.kind {
+            ValueKind::Table(table) => flat.extend(leaves(format!("{key}."), table)),
+            _ => flat.push((key, value)),
+        }
+    }
+    flat
+}
+
+fn set_radius(radius: &mut f64, config: &Config) {
+    if let Some(value) = schema::get_float(config, "frame.radius") {
+        *radius = value;
+    }
+}
+
+fn set_size(size: &mut (u32, u32), config: &Config) {
+    if let Some(height) = schema::get_int(config, "frame.size.height") {
+        size.1 = height as u32;
+    }
+
+    if let Some(width) = schema::get_int(config, "frame.size.width") {
+        size.0 = width as u32;
+    }
+}
+
+fn set_gradient(gradient: &mut Option<Gradient>, config: &Config, background: AlphaColor<Srgb>) {
+    if config.get_table("color.gradient").is_err() {
+        return;
+    }
+
+    let mut from = background;
+    let mut to = background;
+    set_color(&mut from, config, "color.gradient.from");
+    set_color(&mut to, config, "color.gradient.to");
+
+    let kind = config
+        .get_string("color.gradient.kind")
+        .unwrap_or("linear".to_string());
+
+    *gradient = match kind.as_str() {
+        "linear" => Some(Gradient::Linear {
+            from,
+            to,
+            angle: schema::get_float(config, "color.gradient.angle").unwrap_or(90.0),
+        }),
+        "radial" => Some(Gradient::Radial { from, to }),
+        _ => return,
+    }
+}
+
+fn set_border(border: &mut Option<Border>, config: &Config) {
+    if let Some(width) = schema::get_float(config, "border.width") {
+        let mut color = Color::from_rgba8(255, 255, 255, 60);
+        set_color(&mut color, config, "border.color");
+        *border = Some(Border { width, color });
+    }
+}
+
+// The blur is the standard deviation of the gaussian blur applied to the shadow.
+fn set_shadow(shadow: &mut Option<Shadow>, config: &Config) {
+    if config.get_table("shadow").is_err() {
+        return;
+    }
+
+    let mut color = Color::from_rgba8(0, 0, 0, 120);
+    set_color(&mut color, config, "shadow.color");
+
+    let blur = schema::get_float(config, "shadow.blur").unwrap_or(8.0);
+    let offset = (
+        schema::get_float(config, "shadow.offset.x").unwrap_or(0.0),
+        schema::get_float(config, "shadow.offset.y").unwrap_or(4.0),
+    );
+
+    *shadow = Some(Shadow {
+        color,
+        blur,
+        offset,
+    });
+}
+
+fn set_glow(glow: &mut Option<Glow>, config: &Config) {
+    if let Some(width) = schema::get_float(config, "glow.width") {
+        let mut color = Color::from_rgba8(255, 255, 255, 40);
+        set_color(&mut color, config, "glow.color");
+        *glow = Some(Glow { width, color });
+    }
+}
+
+fn set_text(text: &mut Text, config: &Config) {
+    if let Ok(visible) = config.get_bool("text.visible") {
+        text.visible = visible;
+    }
+
+    if let Some(size) = schema::get_float(config, "text.size") {
+        text.size = size as f32;
+    }
+
+    if let Some(offset) = schema::get_float(config, "text.offset") {
+        text.offset = offset as f32;
+    }
+}
+
+fn set_bar(bar: &mut Bar, config: &Config) {
+    if let Ok(visible) = config.get_bool("bar.visible") {
+        bar.visible = visible;
+    }
+
+    if let Some(height) = schema::get_float(config, "bar.height") {
+        bar.height = height;
+    }
+
+    if let Some(margin) = schema::get_float(config, "bar.margin") {
+        bar.margin = margin;
+    }
+
+    set_color(&mut bar.color, config, "bar.color");
+    set_color(&mut bar.track, config, "bar.track");
+}
+
+fn set_palette(palette: &mut Option<Palette>, config: &Config, key: &str, settings: &Settings) {
+    if config.get_table(key).is_err() {
+        return;
+    }
+
+    let mut background = settings.background_color;
+    let mut foreground = settings.foreground_color;
+    set_color(&mut background, config, &format!("{key}.background"));
+    set_color(&mut foreground, config, &format!("{key}.foreground"));
+
+    *palette = Some(Palette {
+        background,
+        foreground,
+    });
+}
+
+fn set_renderer(renderer: &mut Renderer, config: &Config) {
+    if let Ok(value) = config.get_string("renderer") {
+        *renderer = match value.as_str() {
+            "auto" => Renderer::Auto,
+            "gpu" => Renderer::Gpu,
+            "cpu" => Renderer::Cpu,
+            _ => return,
+        }
+    }
+}
+
+fn set_output(output: &mut Output, config: &Config) {
+    if let Ok(value) = config.get_string("output") {
+        *output = match value.as_str() {
+            "focused" => Output::Focused,
+            "all" => Output::All,
+            "" => return,
+            name => Output::Named(name.to_string()),
+        }
+    }
+}
+
+fn set_behavior(behavior: &mut Behavior, config: &Config) {
+    if let Some(millis) = schema::get_int(config, "behavior.timeout_ms") {
+        behavior.timeout = Duration::from_millis(millis as u64);
+    }
+
+    if let Some(millis) = schema::get_int(config, "behavior.event_timeout_ms") {
+        behavior.event_timeout = Duration::from_millis(millis as u64);
+    }
+
+    if let Ok(pin) = config.get_bool("behavior.pin") {
+        behavior.pin = pin;
+    }
+
+    if let Ok(auto_hide) = config.get_bool("behavior.auto_hide") {
+        behavior.auto_hide = auto_hide;
+    }
+
+    if let Ok(interactive) = config.get_bool("behavior.interactive") {
+        behavior.interactive = interactive;
+    }
+}
+
+// A duration of 0 disables the animation between values.
+fn set_transition(transition: &mut Duration, config: &Config) {
+    if let Some(millis) = schema::get_int(config, "animation.duration") {
+        *transition = Duration::from_millis(millis as u64);
+    }
+}
+
+fn set_anchor(anchor: &mut Anchor, config: &Config) {
+    if let Ok(value) = config.get_string("frame.anchor") {
+        *anchor = match value.as_str() {
+            "top-left" => Anchor::TopLeft,
+            "top" | "top-center" => Anchor::Top,
+            "top-right" => Anchor::TopRight,
+            "left" => Anchor::Left,
+            "center" => Anchor::Center,
+            "right" => Anchor::Right,
+            "bottom-left" => Anchor::BottomLeft,
+            "bottom" | "bottom-center" => Anchor::Bottom,
+            "bottom-right" => Anchor::BottomRight,
+            _ => return,
+        }
+    }
+}
+
+fn set_stacking(layer: &mut Stacking, config: &Config) {
+    if let Ok(value) = config.get_string("frame.layer") {
+        *layer = match value.as_str() {
+            "top" => Stacking::Top,
+            "overlay" => Stacking::Overlay,
+            _ => return,
+        }
+    }
+}
+
+// y cannot be negative as the shield
+// is kept away from the edge it's anchored to.
+fn set_position(position: &mut (i32, i32), config: &Config) {
+    if let Some(x) = schema::get_int(config, "frame.position.x") {
+        position.0 = x as i32;
+    }
+
+    if let Some(y) = schema::get_int(config, "frame.position.y") {
+        position.1 = y as i32;
+    }
+}
+
+fn set_color(color: &mut AlphaColor<Srgb>, config: &Config, key: &str) {
+    if let Ok(value) = config.get::<Value>(key) {
+        // Invalid colors are reported by the validation, the color is kept as is.
+        if let Ok(parsed) = colors::parse(&value.kind, &colors::palette(config)) {
+            *color = parsed;
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+// Names a configuration file used in place of the ones found in the XDG directories.
+pub const OVERRIDE: &str = "SHIELD_CONFIG";
+
+// Extensions config-rs knows the format of, tried in this order.
+const EXTENSIONS: [&str; 7] = ["toml", "json", "yaml", "yml", "ini", "ron", "json5"];
+
+// A file the configuration may be read from. The base has no extension unless
+// it was given with one, it's resolved to a file when the settings are loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub base: PathBuf,
+    // Missing files are an error, otherwise they're skipped.
+    pub required: bool,
+}
+
+// Files are listed from the lowest precedence to the highest, the keys of a
+// file override the ones of the files before it:
+//
+// 1. shield/config in every directory of $XDG_CONFIG_DIRS (/etc/xdg when unset),
+//    the first directory taking precedence over the next ones.
+// 2. shield/config in $XDG_CONFIG_HOME (~/.config when unset).
+// 3. The file given on the command line.
+//
+// When $SHIELD_CONFIG is set, the file it names replaces 1 and 2.
+pub fn sources(path: Option<&str>) -> Vec<Source> {
+    sources_from(|name| std::env::var_os(name), path)
+}
+
+fn sources_from(env: impl Fn(&str) -> Option<OsString>, path: Option<&str>) -> Vec<Source> {
+    let mut sources = vec![];
+
+    match env(OVERRIDE).filter(|value| !value.is_empty()) {
+        Some(file) => sources.push(Source {
+            base: PathBuf::from(file),
+            required: true,
+        }),
+        None => {
+            let dirs = config_dirs(&env);
+            let home = config_home_from(&env);
+            for dir in dirs.iter().rev().chain(home.iter()) {
+                sources.push(Source {
+                    base: dir.join("shield").join("config"),
+                    required: false,
+                });
+            }
+        }
+    }
+
+    sources.extend(path.map(|path| Source {
+        base: PathBuf::from(path),
+        required: true,
+    }));
+    sources
+}
+
+pub fn config_home() -> Option<PathBuf> {
+    config_home_from(&|name| std::env::var_os(name))
+}
+
+// Relative paths are ignored, like the specification asks.
+fn config_home_from(env: &impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    env("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+fn config_dirs(env: &impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = env("XDG_CONFIG_DIRS")
+        .map(|dirs| {
+            std::env::split_paths(&dirs)
+                .filter(|dir| dir.is_absolute())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if dirs.is_empty() {
+        vec![PathBuf::from("/etc/xdg")]
+    } else {
+        dirs
+    }
+}
+
+// Finds the file of a base, either the base itself or the base with one of
+// the extensions config-rs can parse.
+pub fn resolve(base: &Path) -> Option<PathBuf> {
+    if base.is_file() {
+        return Some(base.to_path_buf());
+    }
+
+    EXTENSIONS.iter().find_map(|extension| {
+        let mut file = base.as_os_str().to_owned();
+        file.push(".");
+        file.push(extension);
+        let file = PathBuf::from(file);
+        file.is_file().then_some(file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    fn bases(sources: Vec<Source>) -> Vec<PathBuf> {
+        sources.into_iter().map(|source| source.base).collect()
+    }
+
+    #[test]
+    fn system_directories_come_before_the_user_one() {
+        let vars = [
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_DIRS", "/etc/first:/etc/second"),
+        ];
+
+        assert_eq!(
+            bases(sources_from(env(&vars), Some("extra.toml"))),
+            vec![
+                PathBuf::from("/etc/second/shield/config"),
+                PathBuf::from("/etc/first/shield/config"),
+                PathBuf::from("/home/user/.config/shield/config"),
+                PathBuf::from("extra.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_apply_when_the_variables_are_unset_or_relative() {
+        let vars = [("HOME", "/home/user"), ("XDG_CONFIG_HOME", "relative")];
+        let sources = sources_from(env(&vars), None);
+
+        assert_eq!(
+            bases(sources.clone()),
+            vec![
+                PathBuf::from("/etc/xdg/shield/config"),
+                PathBuf::from("/home/user/.config/shield/config"),
+            ]
+        );
+        assert!(sources.iter().all(|source| !source.required));
+    }
+
+    #[test]
+    fn override_replaces_the_discovered_files() {
+        let vars = [
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/home/user/conf"),
+            (OVERRIDE, "/tmp/shield.toml"),
+        ];
+
+        assert_eq!(
+            sources_from(env(&vars), None),
+            vec![Source {
+                base: PathBuf::from("/tmp/shield.toml"),
+                required: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,716 @@
+use config::{Config, Map, Value, ValueKind};
+use std::fmt;
+
+use super::{Profile, colors};
+
+// Every key the configuration understands along with the values it accepts,
+// its default and what it does. The setters bring numbers outside of these
+// bounds back within them, the table is what tells the user about it. The
+// schema and the default configuration printed by the command line are
+// generated from it too.
+pub const FIELDS: &[Field] = &[
+    Field::new(
+        "theme",
+        Kind::String,
+        Literal::Example("\"macos-dark\""),
+        "Sets every visual parameter at once, the keys below override it. Built-in\n\
+         themes are macos-light, macos-dark, high-contrast and minimal-bar, others\n\
+         are looked up in ~/.config/shield/themes/<name>.toml or by path.",
+    ),
+    Field::new(
+        "renderer",
+        Kind::Choice(&["auto", "gpu", "cpu"]),
+        Literal::String("auto"),
+        "auto rasterizes on the CPU and copies the pixels to shared memory when no\n\
+         GPU adapter can present to the surface.",
+    ),
+    Field::new(
+        "output",
+        Kind::String,
+        Literal::String("focused"),
+        "Output the shield is shown on: focused lets the compositor choose, all\n\
+         shows it on every output. Other values are matched against the name of\n\
+         the output (e.g. DP-1) or part of its description.",
+    ),
+    Field::new(
+        "palette",
+        Kind::Palette,
+        Literal::Example("{ accent = \"#2a2849e6\" }"),
+        "Named colors, any color below can refer to them by name.",
+    ),
+    Field::new(
+        "behavior.timeout_ms",
+        Kind::integer(100, 60_000),
+        Literal::Integer(750),
+        "Time in milliseconds the shield stays on screen after a volume change.",
+    ),
+    Field::new(
+        "behavior.event_timeout_ms",
+        Kind::integer(100, 60_000),
+        Literal::Integer(1500),
+        "Time in milliseconds the shield stays after a sink was muted or another\n\
+         sink became the default one.",
+    ),
+    Field::new(
+        "behavior.pin",
+        Kind::Bool,
+        Literal::Bool(true),
+        "Keeps the shield as long as the value changes, otherwise it hides once the\n\
+         timeout elapsed since it appeared.",
+    ),
+    Field::new(
+        "behavior.auto_hide",
+        Kind::Bool,
+        Literal::Bool(true),
+        "Set to false to never hide the shield on its own, e.g. for kiosks.",
+    ),
+    Field::new(
+        "behavior.interactive",
+        Kind::Bool,
+        Literal::Bool(false),
+        "Clicking an interactive shield hides it and scrolling over it changes the\n\
+         volume. Otherwise, the pointer goes through.",
+    ),
+    Field::new(
+        "frame.anchor",
+        Kind::Choice(&[
+            "top-left",
+            "top",
+            "top-center",
+            "top-right",
+            "left",
+            "center",
+            "right",
+            "bottom-left",
+            "bottom",
+            "bottom-center",
+            "bottom-right",
+        ]),
+        Literal::String("bottom"),
+        "Where the shield is anchored on the output.",
+    ),
+    Field::new(
+        "frame.layer",
+        Kind::Choice(&["top", "overlay"]),
+        Literal::String("top"),
+        "Only the overlay layer is shown above fullscreen windows like videos.",
+    ),
+    Field::new(
+        "frame.radius",
+        Kind::float(0.0, 40.0),
+        Literal::Float(14.0),
+        "Radius of the corners.",
+    ),
+    Field::new(
+        "frame.size.width",
+        Kind::integer(100, 800),
+        Literal::Integer(300),
+        "Width of the shield in pixels.",
+    ),
+    Field::new(
+        "frame.size.height",
+        Kind::integer(40, 400),
+        Literal::Integer(200),
+        "Height of the shield in pixels.",
+    ),
+    Field::new(
+        "frame.position.x",
+        Kind::any_integer(),
+        Literal::Integer(0),
+        "Distance in pixels from the edge the shield is anchored to. When the anchor\n\
+         is centered horizontally, distance from the right edge, the shield being\n\
+         centered in the rest of the output.",
+    ),
+    Field::new(
+        "frame.position.y",
+        Kind::Integer {
+            min: Some(0),
+            max: None,
+        },
+        Literal::Integer(250),
+        "Distance in pixels from the edge the shield is anchored to, ignored when\n\
+         the anchor is centered vertically.",
+    ),
+    Field::new(
+        "color.background",
+        Kind::Color,
+        Literal::Color([42, 40, 68, 220]),
+        "Colors are RGB(A) arrays, CSS colors like \"#2a2849e6\" or \"hsl(245, 29%, 22%)\"\n\
+         or the name of a palette entry.",
+    ),
+    Field::new(
+        "color.foreground",
+        Kind::Color,
+        Literal::Color([255, 255, 255, 150]),
+        "Color of the text.",
+    ),
+    Field::new(
+        "color.scheme",
+        Kind::Choice(&["system", "light", "dark"]),
+        Literal::String("system"),
+        "Palette used in place of the colors above, system follows the color-scheme\n\
+         setting of the desktop portal.",
+    ),
+    Field::new(
+        "color.light.background",
+        Kind::Color,
+        Literal::Example("[236, 236, 236, 220]"),
+        "Background when the desktop prefers a light color scheme.",
+    ),
+    Field::new(
+        "color.light.foreground",
+        Kind::Color,
+        Literal::Example("[40, 40, 40, 220]"),
+        "Foreground when the desktop prefers a light color scheme.",
+    ),
+    Field::new(
+        "color.dark.background",
+        Kind::Color,
+        Literal::Example("[30, 30, 30, 210]"),
+        "Background when the desktop prefers a dark color scheme.",
+    ),
+    Field::new(
+        "color.dark.foreground",
+        Kind::Color,
+        Literal::Example("[255, 255, 255, 230]"),
+        "Foreground when the desktop prefers a dark color scheme.",
+    ),
+    Field::new(
+        "color.gradient.kind",
+        Kind::Choice(&["linear", "radial"]),
+        Literal::String("linear"),
+        "Replaces the background color when the section is present.",
+    ),
+    Field::new(
+        "color.gradient.from",
+        Kind::Color,
+        Literal::Example("[52, 50, 90, 230]"),
+        "First color of the gradient, the background color when unset.",
+    ),
+    Field::new(
+        "color.gradient.to",
+        Kind::Color,
+        Literal::Example("[32, 30, 56, 230]"),
+        "Last color of the gradient, the background color when unset.",
+    ),
+    Field::new(
+        "color.gradient.angle",
+        Kind::any_float(),
+        Literal::Float(90.0),
+        "Angle of a linear gradient in degrees, 0 goes from left to right.",
+    ),
+    Field::new(
+        "border.width",
+        Kind::float(0.0, 20.0),
+        Literal::Example("1"),
+        "Draws a border of this width around the shield.",
+    ),
+    Field::new(
+        "border.color",
+        Kind::Color,
+        Literal::Color([255, 255, 255, 60]),
+        "Color of the border.",
+    ),
+    Field::new(
+        "shadow.color",
+        Kind::Color,
+        Literal::Color([0, 0, 0, 120]),
+        "The shield casts a shadow when the section is present.",
+    ),
+    Field::new(
+        "shadow.blur",
+        Kind::float(0.0, 40.0),
+        Literal::Float(8.0),
+        "Standard deviation of the gaussian blur, in pixels.",
+    ),
+    Field::new(
+        "shadow.offset.x",
+        Kind::any_float(),
+        Literal::Float(0.0),
+        "Horizontal offset of the shadow in pixels.",
+    ),
+    Field::new(
+        "shadow.offset.y",
+        Kind::any_float(),
+        Literal::Float(4.0),
+        "Vertical offset of the shadow in pixels.",
+    ),
+    Field::new(
+        "glow.width",
+        Kind::float(0.0, 40.0),
+        Literal::Example("6"),
+        "Draws a glow of this width inside the edges of the shield.",
+    ),
+    Field::new(
+        "glow.color",
+        Kind::Color,
+        Literal::Color([255, 255, 255, 40]),
+        "Color of the glow.",
+    ),
+    Field::new(
+        "text.visible",
+        Kind::Bool,
+        Literal::Bool(true),
+        "Shows the value as a percentage.",
+    ),
+    Field::new(
+        "text.size",
+        Kind::float(8.0, 200.0),
+        Literal::Float(112.0),
+        "Size of the font in pixels.",
+    ),
+    Field::new(
+        "text.offset",
+        Kind::any_float(),
+        Literal::Float(85.0),
+        "Vertical distance between the top of the shield and the text.",
+    ),
+    Field::new(
+        "bar.visible",
+        Kind::Bool,
+        Literal::Bool(false),
+        "Shows the value as a bar at the bottom of the shield.",
+    ),
+    Field::new(
+        "bar.height",
+        Kind::float(1.0, 100.0),
+        Literal::Float(8.0),
+        "Height of the bar in pixels.",
+    ),
+    Field::new(
+        "bar.margin",
+        Kind::float(0.0, 100.0),
+        Literal::Float(24.0),
+        "Distance between the bar and the edges of the shield.",
+    ),
+    Field::new(
+        "bar.color",
+        Kind::Color,
+        Literal::Color([255, 255, 255, 150]),
+        "Color of the filled part of the bar.",
+    ),
+    Field::new(
+        "bar.track",
+        Kind::Color,
+        Literal::Color([255, 255, 255, 40]),
+        "Color of the part of the bar that isn't filled.",
+    ),
+    Field::new(
+        "animation.duration",
+        Kind::integer(0, 2000),
+        Literal::Integer(150),
+        "Duration in milliseconds of the transition between two values, 0 disables it.",
+    ),
+];
+
+// Keys that apply to the whole shield and can't be set in a profile.
+pub const GLOBAL: [&str; 4] = ["theme", "renderer", "output", "palette"];
+
+pub struct Field {
+    pub key: &'static str,
+    pub kind: Kind,
+    pub default: Literal,
+    // Lines of documentation, without the comment markers.
+    pub doc: &'static str,
+}
+
+impl Field {
+    const fn new(key: &'static str, kind: Kind, default: Literal, doc: &'static str) -> Field {
+        Field {
+            key,
+            kind,
+            default,
+            doc,
+        }
+    }
+}
+
+fn field(key: &str) -> &'static Field {
+    FIELDS
+        .iter()
+        .find(|field| field.key == key)
+        .unwrap_or_else(|| panic!("{key} isn't in the schema"))
+}
+
+// Reads the number at the key, brought within the bounds of its field so the
+// setters and the schema can't disagree on them.
+pub fn get_float(config: &Config, key: &str) -> Option<f64> {
+    let Kind::Float { min, max } = &field(key).kind else {
+        panic!("{key} isn't a number in the schema");
+    };
+    let value = config.get_float(key).ok()?;
+    Some(value.clamp(min.unwrap_or(f64::MIN), max.unwrap_or(f64::MAX)))
+}
+
+pub fn get_int(config: &Config, key: &str) -> Option<i64> {
+    let Kind::Integer { min, max } = &field(key).kind else {
+        panic!("{key} isn't an integer in the schema");
+    };
+    let value = config.get_int(key).ok()?;
+    Some(value.clamp(min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX)))
+}
+
+// Value a key has when it isn't set.
+pub enum Literal {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(&'static str),
+    Color([u8; 4]),
+    // The key has no default, either it turns a feature on or it falls back on
+    // another key. The example is written in TOML.
+    Example(&'static str),
+}
+
+pub enum Kind {
+    Bool,
+    // Bounds are inclusive, a missing bound leaves that side open.
+    Integer { min: Option<i64>, max: Option<i64> },
+    Float { min: Option<f64>, max: Option<f64> },
+    String,
+    Choice(&'static [&'static str]),
+    // RGB(A) array of integers between 0 and 255, CSS color or palette entry.
+    Color,
+    // Table of colors that can be referred to by their name.
+    Palette,
+}
+
+impl Kind {
+    const fn integer(min: i64, max: i64) -> Kind {
+        Kind::Integer {
+            min: Some(min),
+            max: Some(max),
+        }
+    }
+
+    const fn any_integer() -> Kind {
+        Kind::Integer {
+            min: None,
+            max: None,
+        }
+    }
+
+    const fn float(min: f64, max: f64) -> Kind {
+        Kind::Float {
+            min: Some(min),
+            max: Some(max),
+        }
+    }
+
+    const fn any_float() -> Kind {
+        Kind::Float {
+            min: None,
+            max: None,
+        }
+    }
+
+    // Describes what the value should have been, None when it's acceptable.
+    fn check(&self, value: &ValueKind, palette: &Map<String, Value>) -> Option<String> {
+        match (self, value) {
+            (Kind::Bool, ValueKind::Boolean(_)) => None,
+            (Kind::Bool, _) => Some("should be true or false".to_string()),
+            (Kind::Integer { min, max }, value) => match integer(value) {
+                Some(value) => range(value, *min, *max),
+                None => Some(format!("should be an integer{}", bounds(*min, *max))),
+            },
+            (Kind::Float { min, max }, value) => match float(value) {
+                Some(value) => range(value, *min, *max),
+                None => Some(format!("should be a number{}", bounds(*min, *max))),
+            },
+            (Kind::String, ValueKind::String(_)) => None,
+            (Kind::String, _) => Some("should be a string".to_string()),
+            (Kind::Choice(choices), ValueKind::String(value))
+                if choices.contains(&value.as_str()) =>
+            {
+                None
+            }
+            (Kind::Choice(choices), _) => Some(format!("should be one of {}", choices.join(", "))),
+            (Kind::Color, value) => colors::parse(value, palette).err(),
+            // Entries are checked one by one.
+            (Kind::Palette, ValueKind::Table(_)) => None,
+            (Kind::Palette, _) => Some("should be a table of colors".to_string()),
+        }
+    }
+}
+
+fn integer(value: &ValueKind) -> Option<i64> {
+    match value {
+        ValueKind::I64(value) => Some(*value),
+        ValueKind::I128(value) => i64::try_from(*value).ok(),
+        ValueKind::U64(value) => i64::try_from(*value).ok(),
+        ValueKind::U128(value) => i64::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+fn float(value: &ValueKind) -> Option<f64> {
+    match value {
+        ValueKind::Float(value) => Some(*value),
+        value => integer(value).map(|value| value as f64),
+    }
+}
+
+fn range<T: PartialOrd + fmt::Display>(value: T, min: Option<T>, max: Option<T>) -> Option<String> {
+    let below = min.as_ref().is_some_and(|min| value < *min);
+    let above = max.as_ref().is_some_and(|max| value > *max);
+    (below || above).then(|| format!("is out of range, it should be{}", bounds(min, max)))
+}
+
+fn bounds<T: fmt::Display>(min: Option<T>, max: Option<T>) -> String {
+    match (min, max) {
+        (Some(min), Some(max)) => format!(" between {min} and {max}"),
+        (Some(min), None) => format!(" of at least {min}"),
+        (None, Some(max)) => format!(" of at most {max}"),
+        (None, None) => String::new(),
+    }
+}
+
+// A value of the configuration that can't be used as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    // File the value comes from, when known.
+    pub origin: Option<String>,
+    pub key: String,
+    pub value: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = &self.origin {
+            write!(f, "{origin}: ")?;
+        }
+        write!(f, "{} = {} {}", self.key, self.value, self.message)
+    }
+}
+
+// Goes through every value of the configuration and reports the keys that
+// aren't known and the values that don't fit their key.
+pub fn validate(config: &Config) -> Vec<Problem> {
+    let mut problems = vec![];
+    let palette = colors::palette(config);
+    if let ValueKind::Table(table) = &config.cache.kind {
+        let mut keys: Vec<_> = table.keys().collect();
+        keys.sort();
+        for key in keys {
+            match (key.as_str(), &table[key].kind) {
+                ("profile", ValueKind::Table(profiles)) => {
+                    visit_profiles(profiles, &palette, &mut problems)
+                }
+                _ => visit("", key, &table[key], &palette, &mut problems),
+            }
+        }
+    }
+    problems
+}
+
+// Profiles take the same keys as the configuration, but the global ones.
+fn visit_profiles(
+    profiles: &Map<String, Value>,
+    palette: &Map<String, Value>,
+    problems: &mut Vec<Problem>,
+) {
+    let mut names: Vec<_> = profiles.keys().collect();
+    names.sort();
+    for name in names {
+        let scope = format!("profile.{name}.");
+        let value = &profiles[name];
+        let table = match &value.kind {
+            ValueKind::Table(table) if Profile::ALL.iter().any(|p| p.name() == name) => table,
+            _ => {
+                let names: Vec<&str> = Profile::ALL.iter().map(Profile::name).collect();
+                let message = format!("is not a profile, profiles are {}", names.join(", "));
+                problems.push(problem(&format!("profile.{name}"), value, message));
+                continue;
+            }
+        };
+
+        let mut keys: Vec<_> = table.keys().collect();
+        keys.sort();
+        for key in keys {
+            if GLOBAL.contains(&key.as_str()) {
+                let message = "can't be set in a profile".to_string();
+                problems.push(problem(&format!("{scope}{key}"), &table[key], message));
+            } else {
+                visit(&scope, key, &table[key], palette, problems);
+            }
+        }
+    }
+}
+
+// The scope is prepended to the key in the problems, it's where the key was found.
+fn visit(
+    scope: &str,
+    key: &str,
+    value: &Value,
+    palette: &Map<String, Value>,
+    problems: &mut Vec<Problem>,
+) {
+    if let Some(field) = FIELDS.iter().find(|field| field.key == key) {
+        if let Some(message) = field.kind.check(&value.kind, palette) {
+            problems.push(problem(&format!("{scope}{key}"), value, message));
+        } else if let (Kind::Palette, ValueKind::Table(entries)) = (&field.kind, &value.kind) {
+            let mut names: Vec<_> = entries.keys().collect();
+            names.sort();
+            for name in names {
+                let entry = &entries[name];
+                if let Err(message) = colors::literal(&entry.kind) {
+                    problems.push(problem(&format!("{key}.{name}"), entry, message));
+                }
+            }
+        }
+        return;
+    }
+
+    let prefix = format!("{key}.");
+    match &value.kind {
+        // Tables are only accepted when they lead to known keys.
+        ValueKind::Table(table) if FIELDS.iter().any(|field| field.key.starts_with(&prefix)) => {
+            let mut keys: Vec<_> = table.keys().collect();
+            keys.sort();
+            for child in keys {
+                visit(
+                    scope,
+                    &format!("{prefix}{child}"),
+                    &table[child],
+                    palette,
+                    problems,
+                );
+            }
+        }
+        _ => problems.push(problem(
+            &format!("{scope}{key}"),
+            value,
+            "is not a known key".to_string(),
+        )),
+    }
+}
+
+fn problem(key: &str, value: &Value, message: String) -> Problem {
+    Problem {
+        origin: value.origin().map(str::to_string),
+        key: key.to_string(),
+        value: show(&value.kind),
+        message,
+    }
+}
+
+// Values are shown the way they're written in TOML.
+fn show(value: &ValueKind) -> String {
+    match value {
+        ValueKind::String(value) => format!("{value:?}"),
+        ValueKind::Array(values) => {
+            let values: Vec<String> = values.iter().map(|value| show(&value.kind)).collect();
+            format!("[{}]", values.join(", "))
+        }
+        ValueKind::Table(_) => "{...}".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::theme;
+    use crate::config::toml_config;
+
+    fn problems(toml: &str) -> Vec<Problem> {
+        validate(&toml_config(toml).unwrap())
+    }
+
+    fn keys(problems: &[Problem]) -> Vec<&str> {
+        problems
+            .iter()
+            .map(|problem| problem.key.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn values_out_of_range_are_reported_with_their_bounds() {
+        let problems = problems("[frame.size]\nwidth = 1200\nheight = 100\n");
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "frame.size.width");
+        assert_eq!(problems[0].value, "1200");
+        assert_eq!(
+            problems[0].message,
+            "is out of range, it should be between 100 and 800"
+        );
+    }
+
+    #[test]
+    fn settings_are_brought_within_the_bounds_of_their_field() {
+        let settings = crate::config::Settings::from_toml(
+            "[frame]\nradius = 100\n[frame.size]\nwidth = 1200\n[text]\nsize = 1\n",
+        )
+        .unwrap();
+
+        assert_eq!(*settings.radius(), 40.0);
+        assert_eq!(settings.size().0, 800);
+        assert_eq!(*settings.text().size(), 8.0);
+    }
+
+    #[test]
+    fn colors_with_invalid_components_are_reported() {
+        let problems = problems("[color]\nbackground = [255, 300, 0]\nforeground = [1, 2]\n");
+
+        assert_eq!(
+            keys(&problems),
+            vec!["color.background", "color.foreground"]
+        );
+        assert_eq!(problems[0].value, "[255, 300, 0]");
+    }
+
+    #[test]
+    fn colors_are_checked_against_the_palette() {
+        let problems = problems(
+            "[palette]\naccent = \"#2a2849\"\nbroken = \"accent\"\n[color]\nbackground = \"accent\"\nforeground = \"nope\"\n",
+        );
+
+        assert_eq!(keys(&problems), vec!["color.foreground", "palette.broken"]);
+    }
+
+    #[test]
+    fn unknown_keys_and_sections_are_reported() {
+        let problems = problems("rendrer = \"cpu\"\n[frame]\nradus = 4\n[bogus]\nkey = 1\n");
+
+        assert_eq!(keys(&problems), vec!["bogus", "frame.radus", "rendrer"]);
+    }
+
+    #[test]
+    fn profiles_are_checked_like_the_configuration() {
+        let problems = problems(
+            "[profile.mute.frame.size]\nwidth = 20\n[profile.mute]\nrenderer = \"cpu\"\n[profile.muted.color]\nbackground = \"red\"\n",
+        );
+
+        assert_eq!(
+            keys(&problems),
+            vec![
+                "profile.mute.frame.size.width",
+                "profile.mute.renderer",
+                "profile.muted"
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_types_and_choices_are_reported() {
+        let problems = problems("renderer = \"vulkan\"\n[text]\nvisible = \"yes\"\n");
+
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].message, "should be one of auto, gpu, cpu");
+        assert_eq!(problems[1].message, "should be true or false");
+    }
+
+    #[test]
+    fn built_in_themes_are_valid() {
+        for name in theme::presets() {
+            let config = Config::builder()
+                .add_source(theme::source(name).unwrap())
+                .build()
+                .unwrap();
+
+            assert_eq!(validate(&config), vec![], "theme {name} has problems");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use anyhow::{Result, anyhow};
+use config::{File, FileFormat, FileSourceString};
+use std::path::PathBuf;
+
+// A theme is a file that describes the visual parameters of the shield using
+// the same keys as the main configuration (frame, color, border, shadow, glow,
+// text and bar). It is loaded underneath the main configuration so any key set
+// there overrides the value coming from the theme.
+//
+// A theme is looked up by name, first among the built-in presets, then in the
+// themes directory of the user: ~/.config/shield/themes/<name>.toml. The name can
+// also be a path to a TOML file.
+const PRESETS: [(&str, &str); 4] = [
+    ("macos-light", include_str!("themes/macos-light.toml")),
+    ("macos-dark", include_str!("themes/macos-dark.toml")),
+    ("high-contrast", include_str!("themes/high-contrast.toml")),
+    ("minimal-bar", include_str!("themes/minimal-bar.toml")),
+];
+
+pub fn source(name: &str) -> Result<File<FileSourceString, FileFormat>> {
+    if let Some((_, preset)) = PRESETS.iter().find(|(preset, _)| *preset == name) {
+        return Ok(File::from_str(preset, FileFormat::Toml));
+    }
+    let path = path(name).unwrap_or_default();
+
+    let content = std::fs::read_to_string(&path).map_err(|err| {
+        let presets: Vec<&str> = presets().collect();
+        anyhow!(
+            "Theme {name:?} couldn't be loaded from {path:?} ({err}), built-in themes are: {}",
+            presets.join(", ")
+        )
+    })?;
+
+    Ok(File::from_str(&content, FileFormat::Toml))
+}
+
+// File of the theme, None for the presets which are built in.
+pub fn path(name: &str) -> Option<PathBuf> {
+    if presets().any(|preset| preset == name) {
+        return None;
+    }
+
+    match themes_dir() {
+        Some(dir) if !name.contains('/') => Some(dir.join(format!("{name}.toml"))),
+        _ => Some(PathBuf::from(name)),
+    }
+}
+
+pub fn presets() -> impl Iterator<Item = &'static str> {
+    PRESETS.iter().map(|(name, _)| *name)
+}
+
+fn themes_dir() -> Option<PathBuf> {
+    Some(super::paths::config_home()?.join("shield").join("themes"))
+}
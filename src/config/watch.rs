@@ -0,0 +1,241 @@
+use anyhow::Result;
+use crossbeam::channel::Sender;
+use rustix::event::{EventfdFlags, PollFd, PollFlags, eventfd, poll};
+use rustix::fd::OwnedFd;
+use rustix::fs::inotify::{self, CreateFlags, ReadFlags, WatchFlags};
+use rustix::io::Errno;
+use std::ffi::{OsStr, OsString};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::app::Event;
+
+// Every directory is watched for the same events. A directory can both hold a
+// file of the configuration and be the closest existing parent of another one,
+// inotify keeps a single set of events per directory.
+const FLAGS: WatchFlags = WatchFlags::CLOSE_WRITE
+    .union(WatchFlags::MOVED_TO)
+    .union(WatchFlags::CREATE)
+    .union(WatchFlags::DELETE);
+
+// Watches the files of the configuration and of the theme. The files can be
+// replaced while the shield runs, e.g. when another theme is picked, the
+// watches are set up again for the new ones.
+pub struct Watcher {
+    sources: Arc<Mutex<Vec<PathBuf>>>,
+    // Written to wake the thread up when the sources changed.
+    wake: Arc<OwnedFd>,
+    _handle: JoinHandle<()>,
+}
+
+impl Watcher {
+    pub fn set_sources(&self, sources: Vec<PathBuf>) {
+        let mut current = self.sources.lock().unwrap();
+        if *current == sources {
+            return;
+        }
+        *current = sources;
+
+        if let Err(err) = rustix::io::write(&*self.wake, &1u64.to_ne_bytes()) {
+            eprintln!("New configuration files can't be watched: {err:?}");
+        }
+    }
+}
+
+// A watch on a directory: either the one of a file, matched by name without its
+// extension like config-rs looks it up, or the closest existing parent of a
+// directory that doesn't exist yet.
+enum Watch {
+    File { descriptor: i32, stem: OsString },
+    Parent { descriptor: i32 },
+}
+
+// Sends a reload event every time one of the files changes. Editors often save
+// by writing a new file and renaming it over the previous one, so the
+// directories are watched rather than the files. A directory that doesn't exist,
+// like ~/.config/shield on a fresh install, is waited for from its closest
+// existing parent.
+pub fn watch(sources: Vec<PathBuf>, sender: Sender<Event>) -> Result<Watcher> {
+    let sources = Arc::new(Mutex::new(sources));
+    let wake = Arc::new(eventfd(0, EventfdFlags::CLOEXEC | EventfdFlags::NONBLOCK)?);
+    // Fails early when inotify isn't available rather than in the thread.
+    drop(inotify::init(CreateFlags::CLOEXEC)?);
+
+    let handle = {
+        let sources = sources.clone();
+        let wake = wake.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = run(&sources, &wake, &sender) {
+                eprintln!("Configuration files aren't watched anymore: {err:?}");
+            }
+        })
+    };
+
+    Ok(Watcher {
+        sources,
+        wake,
+        _handle: handle,
+    })
+}
+
+// The watches are set up from scratch whenever the sources change or a
+// directory on the way to one of them appears or disappears.
+fn run(sources: &Mutex<Vec<PathBuf>>, wake: &OwnedFd, sender: &Sender<Event>) -> Result<()> {
+    let mut inotify = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)?;
+    let mut watches = arm(&inotify, &sources.lock().unwrap())?;
+
+    loop {
+        let wait = wait(&inotify, wake, &watches)?;
+        if matches!(wait, Wait::Rearm | Wait::Woken) {
+            inotify = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)?;
+            watches = arm(&inotify, &sources.lock().unwrap())?;
+        }
+        // Files may have been created along with their directory.
+        if matches!(wait, Wait::Changed | Wait::Rearm) {
+            sender.send(Event::Reload)?;
+        }
+    }
+}
+
+enum Wait {
+    // One of the files changed.
+    Changed,
+    // A directory of the watches appeared or disappeared.
+    Rearm,
+    // The sources were replaced.
+    Woken,
+}
+
+fn wait(inotify: &OwnedFd, wake: &OwnedFd, watches: &[Watch]) -> Result<Wait> {
+    let mut buffer = [MaybeUninit::uninit(); 4096];
+    let mut reader = inotify::Reader::new(inotify, &mut buffer);
+
+    loop {
+        let mut fds = [
+            PollFd::new(inotify, PollFlags::IN),
+            PollFd::new(wake, PollFlags::IN),
+        ];
+        match poll(&mut fds, None) {
+            Ok(_) => {}
+            Err(Errno::INTR) => continue,
+            Err(err) => return Err(err.into()),
+        }
+
+        if !fds[1].revents().is_empty() {
+            let mut count = [0u8; 8];
+            let _ = rustix::io::read(wake, &mut count);
+            return Ok(Wait::Woken);
+        }
+
+        let mut result = None;
+        loop {
+            let event = match reader.next() {
+                Ok(event) => event,
+                Err(Errno::AGAIN) => break,
+                Err(Errno::INTR) => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            if event.events().contains(ReadFlags::IGNORED) {
+                // The directory was removed, its parent is watched instead.
+                result = Some(Wait::Rearm);
+                continue;
+            }
+            let Some(name) = event.file_name() else {
+                continue;
+            };
+            let name = Path::new(OsStr::from_bytes(name.to_bytes()));
+
+            for watch in watches {
+                match watch {
+                    Watch::File { descriptor, stem }
+                        if *descriptor == event.wd()
+                            && name.file_stem() == Some(stem.as_os_str()) =>
+                    {
+                        result.get_or_insert(Wait::Changed);
+                    }
+                    Watch::Parent { descriptor }
+                        if *descriptor == event.wd()
+                            && event.events().contains(ReadFlags::ISDIR) =>
+                    {
+                        result = Some(Wait::Rearm);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(result) = result {
+            return Ok(result);
+        }
+    }
+}
+
+fn arm(inotify: &OwnedFd, sources: &[PathBuf]) -> Result<Vec<Watch>> {
+    let mut watches = vec![];
+    for source in sources {
+        let (Some(dir), Some(stem)) = (source.parent(), source.file_stem()) else {
+            continue;
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+
+        let watch = if dir.is_dir() {
+            inotify::add_watch(inotify, dir, FLAGS).map(|descriptor| Watch::File {
+                descriptor,
+                stem: stem.to_owned(),
+            })
+        } else if let Some(parent) = dir.ancestors().find(|parent| parent.is_dir()) {
+            inotify::add_watch(inotify, parent, FLAGS)
+                .map(|descriptor| Watch::Parent { descriptor })
+        } else {
+            continue;
+        };
+
+        match watch {
+            Ok(watch) => watches.push(watch),
+            // Removed in the meantime, it is left out until the next reload.
+            Err(Errno::NOENT) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(watches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn reloaded(receiver: &crossbeam::channel::Receiver<Event>) -> bool {
+        matches!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(Event::Reload)
+        )
+    }
+
+    #[test]
+    fn directories_created_later_are_watched() {
+        let home = std::env::temp_dir().join(format!("shield-watch-{}", std::process::id()));
+        let dir = home.join("shield");
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(&home).unwrap();
+
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let _watcher = watch(vec![dir.join("config")], sender).unwrap();
+
+        std::fs::create_dir(&dir).unwrap();
+        assert!(reloaded(&receiver));
+
+        std::fs::write(dir.join("config.toml"), "[frame]\nradius = 4\n").unwrap();
+        assert!(reloaded(&receiver));
+
+        let _ = std::fs::remove_dir_all(&home);
+    }
+}
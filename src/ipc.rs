@@ -0,0 +1,89 @@
+use anyhow::{Result, anyhow};
+use crossbeam::channel::Sender;
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+
+use crate::app::Event;
+
+// Commands are sent to the running shield over a unix socket, one command per
+// connection. The shield replies with a single line: ok, or the error.
+pub fn socket_path() -> PathBuf {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join("shield.sock")
+}
+
+// A socket left behind by a previous run is replaced, unless a shield still
+// answers on it.
+pub fn listen(sender: Sender<Event>) -> Result<JoinHandle<()>> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        return Err(anyhow!("Another shield is listening on {path:?}"));
+    }
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    Ok(std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            if let Err(err) = serve(stream, &sender) {
+                eprintln!("IPC command failed: {err:?}");
+            }
+        }
+    }))
+}
+
+fn serve(stream: UnixStream, sender: &Sender<Event>) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut command = String::new();
+    reader.read_line(&mut command)?;
+
+    let reply = match command.trim() {
+        "reload" => {
+            sender.send(Event::Reload)?;
+            "ok".to_string()
+        }
+        command => format!("unknown command: {command:?}"),
+    };
+
+    writeln!(reader.get_mut(), "{reply}")?;
+    Ok(())
+}
+
+// Sends the command to the running shield and returns an error unless it
+// replied ok.
+pub fn send(command: &str) -> Result<()> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|err| anyhow!("No shield is listening on {path:?}: {err}"))?;
+    writeln!(stream, "{command}")?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+
+    match reply.trim() {
+        "ok" => Ok(()),
+        reply => Err(anyhow!("{reply}")),
+    }
+}
+
+// SIGHUP asks for a reload, like most daemons do.
+pub fn on_hangup(sender: Sender<Event>) -> Result<JoinHandle<()>> {
+    let mut signals = Signals::new([SIGHUP])?;
+
+    Ok(std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if sender.send(Event::Reload).is_err() {
+                break;
+            }
+        }
+    }))
+}
@@ -1,4 +1,5 @@
 use super::Layer;
+use super::gpu::Event;
 use smithay_client_toolkit::compositor::CompositorHandler;
 use smithay_client_toolkit::delegate_compositor;
 use wayland_client::protocol::{wl_output, wl_surface};
@@ -9,10 +10,20 @@ impl CompositorHandler for Layer {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
-        // Not needed for this example.
+        let Some(target) = self.target_for(surface) else {
+            return;
+        };
+
+        // The fractional scale takes over when the compositor supports it.
+        if target.scaling().is_fractional() {
+            return;
+        }
+
+        surface.set_buffer_scale(new_factor);
+        target.send(Event::Scale(f64::from(new_factor)));
     }
 
     fn transform_changed(
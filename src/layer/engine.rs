@@ -0,0 +1,139 @@
+use anyhow::Result;
+use crossbeam::channel;
+
+use super::gpu::Event;
+use super::surface::Visibility;
+use crate::shield::Drawing;
+
+// What differs between the engines: where the drawing is rendered to and how
+// it gets on the surface. Sizes are in physical pixels.
+pub trait Backend {
+    fn visibility(&mut self) -> &mut Visibility;
+
+    // Follows a new physical size, the drawing is rendered again right after.
+    fn resize(&mut self, size: (u32, u32)) -> Result<()>;
+
+    fn render(&mut self, drawing: &Drawing, scale: f64) -> Result<()>;
+
+    // The compositor configured the surface or its scale changed.
+    fn configure(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Puts the last rendering on the surface.
+    fn present(&mut self) -> Result<()>;
+
+    // The surface was unmapped.
+    fn hidden(&mut self) {}
+}
+
+// An engine keeps what it knows about the surface and feeds the events to its
+// backend. It runs on the thread of its target.
+pub struct Engine<B> {
+    backend: B,
+    // Size of the surface in logical pixels, the backend is scaled from it.
+    size: (u32, u32),
+    scale: f64,
+    // Whether the compositor configured the surface since it was mapped.
+    ready: bool,
+    // The last drawing is kept so it can be rendered again when the size or
+    // the scale changes.
+    drawing: Option<Drawing>,
+}
+
+// Size in physical pixels of a surface with the given logical size.
+pub fn physical_size(size: (u32, u32), scale: f64) -> (u32, u32) {
+    (
+        (f64::from(size.0) * scale).round() as u32,
+        (f64::from(size.1) * scale).round() as u32,
+    )
+}
+
+impl<B: Backend> Engine<B> {
+    pub fn new(backend: B, size: (u32, u32)) -> Engine<B> {
+        Engine {
+            backend,
+            size,
+            scale: 1.0,
+            ready: false,
+            drawing: None,
+        }
+    }
+
+    // Nothing is presented before the compositor configured the surface, nor
+    // before anything was drawn: an empty surface would still take the clicks.
+    fn paint(&mut self) -> Result<()> {
+        if self.ready && self.drawing.is_some() {
+            self.backend.present()?;
+        }
+        Ok(())
+    }
+
+    // The backend is resized to the new physical size and the last drawing is
+    // rendered again so the surface is never blurry.
+    fn reallocate(&mut self) -> Result<()> {
+        self.backend.resize(physical_size(self.size, self.scale))?;
+
+        if let Some(drawing) = self.drawing.as_ref() {
+            self.backend.render(drawing, self.scale)?;
+        }
+        Ok(())
+    }
+
+    pub fn ingest(&mut self, receiver: &mut channel::Receiver<Event>) -> Result<()> {
+        loop {
+            match receiver.recv()? {
+                Event::Paint => self.paint()?,
+                Event::Render(drawing) => {
+                    self.backend.render(&drawing, self.scale)?;
+                    self.drawing = Some(drawing);
+                }
+                Event::Scale(scale) => {
+                    if scale != self.scale {
+                        self.scale = scale;
+                        self.reallocate()?;
+                        if self.ready {
+                            self.backend.configure()?;
+                        }
+                        self.paint()?;
+                    }
+                }
+                Event::Hide => {
+                    self.ready = false;
+                    self.backend.visibility().unmap()?;
+                    self.backend.hidden();
+                }
+                Event::Show => self.backend.visibility().remap()?,
+                Event::Settings(_) => {}
+                Event::Terminate => {
+                    break;
+                }
+                Event::Configure(width, height) => {
+                    // The compositor can give the surface a size other than the one requested.
+                    if (width, height) != self.size {
+                        self.size = (width, height);
+                        self.reallocate()?;
+                    }
+                    self.ready = true;
+                    self.backend.configure()?;
+                    self.paint()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn physical_size_is_rounded_to_the_nearest_pixel() {
+        assert_eq!(physical_size((300, 200), 1.0), (300, 200));
+        assert_eq!(physical_size((300, 200), 2.0), (600, 400));
+        assert_eq!(physical_size((301, 199), 1.5), (452, 299));
+        assert_eq!(physical_size((300, 201), 1.25), (375, 251));
+    }
+}
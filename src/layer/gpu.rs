@@ -1,29 +1,43 @@
+use super::engine::{self, Engine};
+use super::surface::Visibility;
 use crate::config::Settings;
+use crate::shield::Drawing;
 use anyhow::{Result, anyhow};
-use crossbeam::channel;
 use std::num::NonZeroU32;
 use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use vello::{Renderer, peniko};
+use vello::kurbo::Affine;
+use vello::{Renderer, Scene, peniko};
 use wgpu::util::TextureBlitter;
 use wgpu::{self, Adapter, Backends, Device, Instance, Queue, Surface, Texture, TextureUsages};
 
+#[derive(Clone)]
 pub enum Event {
-    Configure,
+    // Size of the surface in logical pixels, as configured by the compositor.
+    Configure(u32, u32),
     Paint,
-    Render(vello::Scene),
+    Render(Drawing),
+    // Scale factor of the surface, fractional when the compositor supports it.
+    Scale(f64),
+    // The surface stays alive while it's hidden so showing it again doesn't
+    // have to go through the creation of the device and the renderer.
+    Hide,
+    Show,
+    // Settings read again from the configuration files. They're applied by
+    // the layer, engines follow the size through the next configure.
+    Settings(Arc<Settings>),
     Terminate,
 }
 
-pub struct Engine<'a> {
+pub struct Backend<'a> {
     device: Device,
     adapter: Adapter,
     queue: Queue,
     surface: Surface<'a>,
-    ready: Arc<AtomicBool>,
-    size: (u32, u32),
+    visibility: Visibility,
     renderer: Renderer,
     texture: Texture,
+    // Size of the back buffer in physical pixels.
+    size: (u32, u32),
 }
 
 pub fn get_instance() -> Instance {
@@ -33,11 +47,37 @@ pub fn get_instance() -> Instance {
     })
 }
 
+// Drawings are built in logical pixels, their scenes are scaled up to physical
+// pixels right before being rendered.
+pub fn scale_scene(scene: &Scene, scale: f64) -> Scene {
+    let mut scaled = Scene::new();
+    scaled.append(scene, Some(Affine::scale(scale)));
+    scaled
+}
+
+fn back_buffer(device: &Device, size: (u32, u32)) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        label: Some("Back Buffer"),
+        view_formats: &[],
+    })
+}
+
 pub fn create<'a>(
     settings: &Arc<Settings>,
     surface: Surface<'a>,
+    visibility: Visibility,
     instance: Instance,
-) -> Result<Engine<'a>> {
+) -> Result<Engine<Backend<'a>>> {
     // Pick a supported adapter
     let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
         compatible_surface: Some(&surface),
@@ -53,43 +93,81 @@ pub fn create<'a>(
     ))?;
 
     let renderer = Renderer::new(&device, vello::RendererOptions::default())?;
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        size: wgpu::Extent3d {
-            width: settings.size().0,
-            height: settings.size().1,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
-        label: Some("Back Buffer"),
-        view_formats: &[],
-    });
+    let size = settings.surface_size();
+    let texture = back_buffer(&device, size);
 
-    Ok(Engine {
+    let backend = Backend {
         device,
         adapter,
         queue,
         renderer,
         surface,
+        visibility,
         texture,
-        ready: Arc::new(false.into()),
-        size: *settings.size(),
-    })
+        size,
+    };
+    Ok(Engine::new(backend, size))
 }
 
-impl Engine<'_> {
-    pub fn paint(&self, staged_view: wgpu::TextureView) {
-        let texture = self
-            .surface
-            .get_current_texture()
-            .expect("failed to acquire next swapchain texture");
+impl engine::Backend for Backend<'_> {
+    fn visibility(&mut self) -> &mut Visibility {
+        &mut self.visibility
+    }
+
+    fn resize(&mut self, size: (u32, u32)) -> Result<()> {
+        self.texture.destroy();
+        self.texture = back_buffer(&self.device, size);
+        self.size = size;
+        Ok(())
+    }
+
+    fn render(&mut self, drawing: &Drawing, scale: f64) -> Result<()> {
+        let (width, height) = self.size;
+        self.renderer.render_to_texture(
+            &self.device,
+            &self.queue,
+            &scale_scene(drawing.scene(), scale),
+            &self
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            &vello::RenderParams {
+                base_color: peniko::color::palette::css::TRANSPARENT,
+                width,
+                height,
+                antialiasing_method: vello::AaConfig::Msaa16,
+            },
+        )?;
+        Ok(())
+    }
 
+    fn configure(&mut self) -> Result<()> {
+        let width = NonZeroU32::new(self.size.0).map_or(256, NonZeroU32::get);
+        let height = NonZeroU32::new(self.size.1).map_or(256, NonZeroU32::get);
+
+        let cap = self.surface.get_capabilities(&self.adapter);
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+            format: cap.formats[0],
+            view_formats: vec![cap.formats[0]],
+            alpha_mode: wgpu::CompositeAlphaMode::PreMultiplied,
+            width,
+            height,
+            desired_maximum_frame_latency: 2,
+            present_mode: wgpu::PresentMode::Mailbox,
+        };
+
+        self.surface.configure(&self.device, &surface_config);
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<()> {
+        let texture = self.surface.get_current_texture()?;
         let surface_texture = texture
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        let staged_view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder = self
             .device
@@ -103,79 +181,36 @@ impl Engine<'_> {
         self.queue.submit(Some(encoder.finish()));
 
         texture.present();
-    }
-
-    pub fn ingest(&mut self, receiver: &mut channel::Receiver<Event>) -> Result<()> {
-        use std::sync::atomic::Ordering;
-
-        loop {
-            match receiver.recv()? {
-                Event::Paint => {
-                    if self.ready.load(Ordering::Relaxed) {
-                        self.paint(
-                            self.texture
-                                .create_view(&wgpu::TextureViewDescriptor::default()),
-                        );
-                    }
-                }
-
-                Event::Render(scene) => {
-                    self.renderer
-                        .render_to_texture(
-                            &self.device,
-                            &self.queue,
-                            &scene,
-                            &self
-                                .texture
-                                .create_view(&wgpu::TextureViewDescriptor::default()),
-                            &vello::RenderParams {
-                                base_color: peniko::color::palette::css::TRANSPARENT,
-                                width: self.size.0,
-                                height: self.size.1,
-                                antialiasing_method: vello::AaConfig::Msaa16,
-                            },
-                        )
-                        .expect("Failed to render to a texture");
-                }
-                Event::Terminate => {
-                    break;
-                }
-                Event::Configure => {
-                    let width = NonZeroU32::new(self.size.0).map_or(256, NonZeroU32::get);
-                    let height = NonZeroU32::new(self.size.1).map_or(256, NonZeroU32::get);
-
-                    let adapter = &self.adapter;
-                    let surface = &self.surface;
-                    let device = &self.device;
-
-                    let cap = surface.get_capabilities(adapter);
-                    let surface_config = wgpu::SurfaceConfiguration {
-                        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
-                        format: cap.formats[0],
-                        view_formats: vec![cap.formats[0]],
-                        alpha_mode: wgpu::CompositeAlphaMode::PreMultiplied,
-                        width: width,
-                        height: height,
-                        desired_maximum_frame_latency: 2,
-                        present_mode: wgpu::PresentMode::Mailbox,
-                    };
-
-                    surface.configure(device, &surface_config);
-                    self.ready.store(true, Ordering::Relaxed);
-                    self.paint(
-                        self.texture
-                            .create_view(&wgpu::TextureViewDescriptor::default()),
-                    );
-                }
-            }
-        }
-
         Ok(())
     }
 }
 
-impl Drop for Engine<'_> {
+impl Drop for Backend<'_> {
     fn drop(&mut self) {
         self.texture.destroy();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vello::kurbo::Rect;
+    use vello::peniko::{Fill, color::palette};
+
+    #[test]
+    fn scenes_are_scaled_to_physical_pixels() {
+        let mut scene = Scene::new();
+        scene.fill(
+            Fill::NonZero,
+            Affine::translate((4.0, 2.0)),
+            palette::css::WHITE,
+            None,
+            &Rect::new(0.0, 0.0, 10.0, 10.0),
+        );
+
+        let transforms = &scale_scene(&scene, 2.0).encoding().transforms;
+        assert_eq!(transforms.len(), 1);
+        assert_eq!(transforms[0].matrix, [2.0, 0.0, 0.0, 2.0]);
+        assert_eq!(transforms[0].translation, [8.0, 4.0]);
+    }
+}
@@ -1,26 +1,41 @@
 use anyhow::{Result, anyhow};
-use crossbeam::channel;
 use smithay_client_toolkit::compositor::CompositorState;
 use smithay_client_toolkit::delegate_registry;
 use smithay_client_toolkit::output::OutputState;
+use smithay_client_toolkit::reexports::calloop::{EventLoop, LoopSignal, channel};
+use smithay_client_toolkit::reexports::calloop_wayland_source::WaylandSource;
 use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
-use smithay_client_toolkit::registry_handlers;
-use smithay_client_toolkit::shell::wlr_layer::{LayerShell, LayerSurface};
+use smithay_client_toolkit::seat::SeatState;
+use smithay_client_toolkit::shell::wlr_layer::LayerShell;
+use smithay_client_toolkit::shm::{Shm, ShmHandler};
+use smithay_client_toolkit::{delegate_shm, registry_handlers};
 use std::sync::Arc;
 use std::thread::JoinHandle;
 use wayland_client::globals::{GlobalList, registry_queue_init};
-use wayland_client::{Connection, EventQueue};
-use wgpu;
+use wayland_client::protocol::{wl_output, wl_pointer, wl_surface};
+use wayland_client::{Connection, EventQueue, QueueHandle};
 
 mod compositor;
+mod engine;
 pub mod gpu;
+pub mod offscreen;
 use gpu::Event;
 
-use crate::config::Settings;
+use crate::app;
+use crate::config::{Output, Settings};
+use crate::shield::Drawing;
 
 mod output;
+mod scale;
+mod seat;
 mod shell_handler;
+mod shm;
 mod surface;
+mod target;
+use target::Target;
+
+#[cfg(test)]
+mod tests;
 
 #[allow(dead_code)]
 pub struct Layer {
@@ -30,27 +45,61 @@ pub struct Layer {
     layer_shell: LayerShell,
     registry_state: RegistryState,
     output_state: OutputState,
+    seat_state: SeatState,
+    pointers: Vec<wl_pointer::WlPointer>,
     compositor: CompositorState,
-    receiver: channel::Receiver<Event>,
+    shm: Shm,
+    scale: scale::Globals,
+    // Events coming from the app are dispatched by the event loop of the layer and
+    // broadcast to the engine of every target.
+    receiver: Option<channel::Channel<Event>>,
     sender: channel::Sender<Event>,
+    signal: Option<LoopSignal>,
+    // Events coming from the interactions with the shield go back to the app.
+    app: crossbeam::channel::Sender<app::Event>,
 
-    layer_surface: Option<LayerSurface>,
+    targets: Vec<Target>,
+    // Set between Hide and Show. Settings reloaded meanwhile are applied when
+    // the surfaces are shown again, committing would map them.
+    hidden: bool,
+    stale: bool,
+    // Outputs of the targets closed by the compositor while the shield was
+    // hidden, recreated on the next show.
+    closed: Vec<Option<wl_output::WlOutput>>,
+    // The last drawing is given to the targets created afterward, so they don't
+    // wait for the next frame of the app to show something.
+    drawing: Option<Drawing>,
+    queue_handle: QueueHandle<Self>,
 
     // Queue will be consumed when run() is called
     queue: Option<EventQueue<Self>>,
 }
 
 impl Layer {
-    pub fn new(settings: Arc<Settings>) -> Result<Layer> {
-        let connection = Connection::connect_to_env()?;
+    pub fn new(
+        settings: Arc<Settings>,
+        app: crossbeam::channel::Sender<app::Event>,
+    ) -> Result<Layer> {
+        Self::with_connection(Connection::connect_to_env()?, settings, app)
+    }
 
-        let (sender, receiver) = channel::unbounded();
+    // The connection can come from anywhere, the tests connect the layer to a
+    // compositor running in the same process.
+    pub fn with_connection(
+        connection: Connection,
+        settings: Arc<Settings>,
+        app: crossbeam::channel::Sender<app::Event>,
+    ) -> Result<Layer> {
+        let (sender, receiver) = channel::channel();
         let (global, queue) = registry_queue_init(&connection)?;
         let handle = queue.handle();
         let layer_shell = LayerShell::bind(&global, &handle)?;
         let compositor = CompositorState::bind(&global, &handle)?;
         let registry_state = RegistryState::new(&global);
         let output_state = OutputState::new(&global, &handle);
+        let seat_state = SeatState::new(&global, &handle);
+        let shm = Shm::bind(&global, &handle)?;
+        let scale = scale::Globals::bind(&global, &handle);
 
         let layer = Layer {
             connection: connection.clone(),
@@ -58,70 +107,232 @@ impl Layer {
             registry_state,
             compositor,
             output_state,
+            seat_state,
+            pointers: vec![],
             layer_shell,
-            receiver,
+            shm,
+            scale,
+            receiver: Some(receiver),
             sender,
+            signal: None,
+            app,
             settings: settings,
             queue: Some(queue),
-            layer_surface: None,
+            targets: vec![],
+            hidden: false,
+            stale: false,
+            closed: vec![],
+            drawing: None,
+            queue_handle: handle.clone(),
         };
 
         Ok(layer)
     }
 
     pub fn run(mut self) -> Result<Wire> {
-        let instance = gpu::get_instance();
         let mut queue = self.queue.take().ok_or(anyhow!("Layer already ran"))?;
-        let (layer_surface, surface) = surface::Builder::default()
-            .with_connection(&self.connection)
-            .with_layer_shell(&self.layer_shell)
-            .with_compositor(&self.compositor)
-            .with_queue_handle(&queue.handle())
-            .with_settings(&self.settings)
-            .with_instance(&instance)
-            .create()?;
-
-        self.layer_surface = Some(layer_surface);
-        let mut engine = gpu::create(&self.settings, surface, instance)?;
-
-        let (cancel_tx, cancel_rx) = channel::bounded(1);
-        let renderer_handle = {
-            let mut receiver = self.receiver.clone();
-
-            std::thread::spawn(move || {
-                engine.ingest(&mut receiver).unwrap();
-                cancel_tx.send(true);
-            })
-        };
+        let qh = queue.handle();
 
+        // Names and descriptions of the outputs are only known after a roundtrip.
+        queue.roundtrip(&mut self)?;
+
+        self.create_targets(&qh)?;
+
+        let receiver = self.receiver.take().ok_or(anyhow!("Layer already ran"))?;
         let sender = self.sender.clone();
+
+        // The loop sleeps until the Wayland socket is readable or the app sends
+        // an event, and stops as soon as it's told to terminate. It has no timer
+        // of its own: the deadlines for frames and hiding belong to the app, which
+        // outlives the layer and decides what's shown (see timer.rs). The layer
+        // only acts on the events they produce.
         let handle = std::thread::spawn(move || {
-            loop {
-                if let Ok(_) = cancel_rx.try_recv() {
-                    break;
-                }
-                // This is a busy loop for now and needs to be optimized
-                // so it can use the underlying FD and sleep until it can be
-                // woken up when the FD is ready to be process an event.
-                // For this to work, the future will need to wait for the FD but
-                // something will need to also challenge the read for the cancel_rx,
-                // like crossbeam's select! so that the loop needs to be cancelled,
-                // this loop doesn't need to wait for a wayland event to wake up.
-                queue.flush().unwrap();
-                queue.dispatch_pending(&mut self).unwrap();
-                let guard = queue.prepare_read().unwrap();
-                guard.read().unwrap();
-                queue.dispatch_pending(&mut self).unwrap();
-            }
-            if let Some(layer_surface) = self.layer_surface.take() {
-                drop(queue);
-                drop(layer_surface);
+            if let Err(err) = self.event_loop(queue, receiver) {
+                eprintln!("Layer stopped: {err:?}");
             }
-            renderer_handle.join();
+            self.targets.clear();
         });
 
         Ok(Wire { handle, sender })
     }
+
+    // Places the targets on the outputs picked in the settings, or lets the
+    // compositor pick one.
+    fn create_targets(&mut self, qh: &QueueHandle<Self>) -> Result<()> {
+        match self.settings.output().clone() {
+            Output::All => {
+                let outputs: Vec<_> = self.output_state.outputs().collect();
+                for output in outputs {
+                    self.show_on(Some(output), qh)?;
+                }
+            }
+            Output::Named(name) => {
+                let output = self.find_output(&name);
+                if output.is_none() {
+                    eprintln!("No output matches {name:?}, the compositor will pick one");
+                }
+                self.show_on(output, qh)?;
+            }
+            Output::Focused => self.show_on(None, qh)?,
+        }
+
+        if self.targets.is_empty() {
+            self.show_on(None, qh)?;
+        }
+
+        Ok(())
+    }
+
+    fn event_loop(
+        &mut self,
+        queue: EventQueue<Self>,
+        receiver: channel::Channel<Event>,
+    ) -> Result<()> {
+        let mut event_loop: EventLoop<Layer> = EventLoop::try_new()?;
+        let handle = event_loop.handle();
+
+        WaylandSource::new(self.connection.clone(), queue)
+            .insert(handle.clone())
+            .map_err(|err| anyhow!("Wayland source can't be inserted: {}", err.error))?;
+        handle
+            .insert_source(receiver, |event, _, layer| match event {
+                channel::Event::Msg(event) => layer.dispatch(event),
+                channel::Event::Closed => layer.stop(),
+            })
+            .map_err(|err| anyhow!("Channel can't be inserted: {}", err.error))?;
+
+        self.signal = Some(event_loop.get_signal());
+        event_loop.run(None, self, |_| {})?;
+
+        Ok(())
+    }
+
+    fn dispatch(&mut self, event: Event) {
+        match &event {
+            Event::Settings(settings) => return self.replace_settings(settings.clone()),
+            Event::Render(drawing) => self.drawing = Some(drawing.clone()),
+            Event::Hide => self.hidden = true,
+            Event::Show => {
+                self.hidden = false;
+                self.reopen();
+                if std::mem::take(&mut self.stale) {
+                    self.place_targets();
+                }
+            }
+            _ => {}
+        }
+
+        let terminate = matches!(event, Event::Terminate);
+        for target in &self.targets {
+            target.send(event.clone());
+        }
+
+        if terminate {
+            self.stop();
+        }
+    }
+
+    // Surfaces are moved in place. A change of outputs or renderer needs
+    // new targets.
+    fn replace_settings(&mut self, settings: Arc<Settings>) {
+        let recreate = settings.output() != self.settings.output()
+            || settings.renderer() != self.settings.renderer();
+        self.settings = settings;
+
+        if recreate {
+            let qh = self.queue_handle.clone();
+            self.targets.clear();
+            self.closed.clear();
+            if let Err(err) = self.create_targets(&qh) {
+                eprintln!("Can't recreate the surfaces: {err:?}");
+            }
+        } else if self.hidden {
+            self.stale = true;
+        } else {
+            self.place_targets();
+        }
+    }
+
+    fn place_targets(&self) {
+        for target in &self.targets {
+            if let Err(err) = target.place(&self.settings, &self.compositor) {
+                eprintln!("Can't apply the settings to the surface: {err:?}");
+            }
+        }
+        let _ = self.connection.flush();
+    }
+
+    fn reopen(&mut self) {
+        let qh = self.queue_handle.clone();
+        for output in std::mem::take(&mut self.closed) {
+            if let Err(err) = self.show_on(output, &qh) {
+                eprintln!("Can't recreate the surface closed by the compositor: {err:?}");
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(signal) = self.signal.as_ref() {
+            signal.stop();
+        }
+    }
+
+    // Creates a target on the output, unless there's already one there. It's
+    // hidden right away if the shield is, e.g. when an output is plugged or the
+    // settings change meanwhile.
+    fn show_on(
+        &mut self,
+        output: Option<wl_output::WlOutput>,
+        qh: &QueueHandle<Self>,
+    ) -> Result<()> {
+        if self
+            .targets
+            .iter()
+            .any(|target| target.output() == output.as_ref())
+        {
+            return Ok(());
+        }
+
+        let target = Target::create(self, output, qh)?;
+        if let Some(drawing) = self.drawing.as_ref() {
+            target.send(Event::Render(drawing.clone()));
+        }
+        if self.hidden {
+            target.send(Event::Hide);
+        }
+        self.targets.push(target);
+
+        Ok(())
+    }
+
+    // Outputs are matched by their name (e.g. DP-1) or by part of their
+    // description. A name is looked for on every output first, so DP-1 isn't
+    // taken by an output described as "... (DP-10)".
+    fn find_output(&self, name: &str) -> Option<wl_output::WlOutput> {
+        let infos: Vec<_> = self
+            .output_state
+            .outputs()
+            .filter_map(|output| Some((self.output_state.info(&output)?, output)))
+            .collect();
+
+        let named = infos
+            .iter()
+            .find(|(info, _)| info.name.as_deref() == Some(name));
+        let described = || {
+            infos.iter().find(|(info, _)| {
+                info.description
+                    .as_deref()
+                    .is_some_and(|description| description.contains(name))
+            })
+        };
+        named.or_else(described).map(|(_, output)| output.clone())
+    }
+
+    fn target_for(&self, surface: &wl_surface::WlSurface) -> Option<&Target> {
+        self.targets
+            .iter()
+            .find(|target| target.has_surface(surface))
+    }
 }
 
 #[allow(dead_code)]
@@ -140,7 +351,15 @@ impl ProvidesRegistryState for Layer {
     fn registry(&mut self) -> &mut RegistryState {
         &mut self.registry_state
     }
-    registry_handlers![OutputState];
+    registry_handlers![OutputState, SeatState];
 }
 
 delegate_registry!(Layer);
+
+impl ShmHandler for Layer {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+delegate_shm!(Layer);
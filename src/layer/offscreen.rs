@@ -0,0 +1,439 @@
+use anyhow::{Result, anyhow};
+use skrifa::instance::{LocationRef, Size};
+use skrifa::outline::{DrawSettings, OutlinePen};
+use skrifa::{FontRef, GlyphId, MetadataProvider};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use tiny_skia::{
+    FillRule, GradientStop, LinearGradient, Mask, Paint, PathBuilder, Pixmap, Point,
+    RadialGradient, Shader, SpreadMode, Transform,
+};
+use vello::kurbo::{Affine, BezPath, PathEl, Rect};
+use vello::peniko::color::Srgb;
+use vello::peniko::{self, Brush, Color, Extend, GradientKind};
+
+use crate::shield::{Command, Drawing};
+
+// Offscreen rasterizes drawings on the CPU into a pixmap that isn't attached to
+// any surface. Nothing goes through wgpu, so it renders the same way on every
+// machine, with or without a GPU.
+pub struct Offscreen {
+    pixmap: Pixmap,
+    // Masks of the clips pushed so far, each already intersected with the
+    // previous ones.
+    clips: Vec<Mask>,
+}
+
+impl Offscreen {
+    pub fn new(size: (u32, u32)) -> Result<Offscreen> {
+        Ok(Offscreen {
+            pixmap: pixmap(size)?,
+            clips: vec![],
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.pixmap.width(), self.pixmap.height())
+    }
+
+    pub fn resize(&mut self, size: (u32, u32)) -> Result<()> {
+        if size != self.size() {
+            self.pixmap = pixmap(size)?;
+        }
+        Ok(())
+    }
+
+    // Drawings are in logical pixels, they are scaled up to physical pixels
+    // while being rasterized.
+    pub fn render(&mut self, drawing: &Drawing, scale: f64) {
+        let scale = Affine::scale(scale);
+        self.pixmap.fill(tiny_skia::Color::TRANSPARENT);
+        self.clips.clear();
+
+        for command in drawing.commands() {
+            match command {
+                Command::Fill {
+                    transform,
+                    brush,
+                    path,
+                } => self.fill(scale * *transform, brush, path),
+                Command::Stroke {
+                    transform,
+                    width,
+                    brush,
+                    path,
+                } => self.stroke(scale * *transform, *width, brush, path),
+                Command::BlurredRect {
+                    transform,
+                    rect,
+                    color,
+                    radius,
+                    blur,
+                } => self.blurred_rect(scale * *transform, *rect, *color, *radius, *blur),
+                Command::Glyphs {
+                    transform,
+                    glyph_transform,
+                    font,
+                    size,
+                    coords,
+                    brush,
+                    glyphs,
+                } => {
+                    let Ok(font) = FontRef::from_index(font.data.data(), font.index) else {
+                        continue;
+                    };
+                    let outlines = font.outline_glyphs();
+                    let settings =
+                        || DrawSettings::unhinted(Size::new(*size), LocationRef::new(coords));
+
+                    for glyph in glyphs {
+                        let Some(outline) = outlines.get(GlyphId::new(glyph.id)) else {
+                            continue;
+                        };
+                        let mut pen = Pen(BezPath::new());
+                        if outline.draw(settings(), &mut pen).is_err() {
+                            continue;
+                        }
+                        let transform = scale
+                            * *transform
+                            * Affine::translate((f64::from(glyph.x), f64::from(glyph.y)))
+                            * Affine::FLIP_Y
+                            * glyph_transform.unwrap_or(Affine::IDENTITY);
+                        self.fill(transform, brush, &pen.0);
+                    }
+                }
+                Command::PushClip { transform, path } => {
+                    let Some(path) = path_of(path) else {
+                        continue;
+                    };
+                    let mut mask = match self.clips.last() {
+                        Some(clip) => clip.clone(),
+                        None => {
+                            let mut mask = Mask::new(self.pixmap.width(), self.pixmap.height())
+                                .expect("the mask has the size of the pixmap");
+                            mask.invert();
+                            mask
+                        }
+                    };
+                    mask.intersect_path(
+                        &path,
+                        FillRule::Winding,
+                        true,
+                        transform_of(scale * *transform),
+                    );
+                    self.clips.push(mask);
+                }
+                Command::PopClip => {
+                    self.clips.pop();
+                }
+            }
+        }
+    }
+
+    // The pixels as RGBA with premultiplied alpha, row after row.
+    pub fn premultiplied(&self) -> &[u8] {
+        self.pixmap.data()
+    }
+
+    // The pixels as RGBA with straight alpha, row after row.
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect()
+    }
+
+    fn fill(&mut self, transform: Affine, brush: &Brush, path: &BezPath) {
+        let (Some(path), Some(paint)) = (path_of(path), paint_of(brush)) else {
+            return;
+        };
+        self.pixmap.fill_path(
+            &path,
+            &paint,
+            FillRule::Winding,
+            transform_of(transform),
+            self.clips.last(),
+        );
+    }
+
+    fn stroke(&mut self, transform: Affine, width: f64, brush: &Brush, path: &BezPath) {
+        let (Some(path), Some(paint)) = (path_of(path), paint_of(brush)) else {
+            return;
+        };
+        // Round like the strokes of kurbo that vello draws.
+        let stroke = tiny_skia::Stroke {
+            width: width as f32,
+            line_cap: tiny_skia::LineCap::Round,
+            line_join: tiny_skia::LineJoin::Round,
+            ..Default::default()
+        };
+        self.pixmap.stroke_path(
+            &path,
+            &paint,
+            &stroke,
+            transform_of(transform),
+            self.clips.last(),
+        );
+    }
+
+    // The coverage of a blurred rounded rectangle has no closed form, it's
+    // approximated the way vello does on the GPU: the distance to a
+    // superellipse fitted to the corners goes through the error function.
+    fn blurred_rect(
+        &mut self,
+        transform: Affine,
+        rect: Rect,
+        color: Color,
+        radius: f64,
+        blur: f64,
+    ) {
+        let Some(inverse) = invertible(transform) else {
+            return;
+        };
+        let blur = blur.max(1e-3);
+        let (width, height) = (rect.width(), rect.height());
+        let max_radius = 0.5 * width.min(height);
+        let r0 = radius.hypot(1.15 * blur).min(max_radius);
+        let r1 = radius.hypot(2.0 * blur).min(max_radius);
+        let exponent = 2.0 * r1 / r0;
+        let shrink = |extent: f64| {
+            let delta = 1.25 * blur * ((-(0.5 / blur * extent).powi(2)).exp() - 1.0);
+            extent + delta.min(0.0)
+        };
+        let (shrunk_width, shrunk_height) = (shrink(width), shrink(height));
+        let min_edge = shrunk_width.min(shrunk_height);
+        let scale = 1.0 / (std::f64::consts::SQRT_2 * blur);
+
+        let bounds = transform
+            .transform_rect_bbox(rect.inflate(3.0 * blur, 3.0 * blur))
+            .intersect(Rect::new(
+                0.0,
+                0.0,
+                f64::from(self.pixmap.width()),
+                f64::from(self.pixmap.height()),
+            ));
+        if bounds.is_zero_area() {
+            return;
+        }
+
+        let components = color.premultiply().components;
+        let stride = self.pixmap.width() as usize;
+        let clip = self.clips.last().map(|mask| mask.data());
+        let pixels = self.pixmap.pixels_mut();
+
+        for y in bounds.y0.floor() as usize..bounds.y1.ceil() as usize {
+            for x in bounds.x0.floor() as usize..bounds.x1.ceil() as usize {
+                let point = inverse * vello::kurbo::Point::new(x as f64 + 0.5, y as f64 + 0.5);
+                let x0 = (point.x - rect.x0 - width / 2.0).abs() + r1 - shrunk_width / 2.0;
+                let y0 = (point.y - rect.y0 - height / 2.0).abs() + r1 - shrunk_height / 2.0;
+                let distance = (x0.max(0.0).powf(exponent) + y0.max(0.0).powf(exponent))
+                    .powf(1.0 / exponent)
+                    + x0.max(y0).min(0.0)
+                    - r1;
+                let coverage = 0.5 * (erf(scale * (min_edge + distance)) - erf(scale * distance));
+
+                let index = y * stride + x;
+                let coverage = match clip {
+                    Some(clip) => coverage * f64::from(clip[index]) / 255.0,
+                    None => coverage,
+                } as f32;
+                if coverage <= 0.0 {
+                    continue;
+                }
+
+                // Source over, in premultiplied alpha.
+                let pixel = &mut pixels[index];
+                let source = components.map(|channel| channel * coverage);
+                let keep = 1.0 - source[3];
+                let blend = |source: f32, destination: u8| {
+                    (source * 255.0 + f32::from(destination) * keep).round() as u8
+                };
+                let alpha = blend(source[3], pixel.alpha());
+                *pixel = tiny_skia::PremultipliedColorU8::from_rgba(
+                    blend(source[0], pixel.red()).min(alpha),
+                    blend(source[1], pixel.green()).min(alpha),
+                    blend(source[2], pixel.blue()).min(alpha),
+                    alpha,
+                )
+                .expect("the channels are capped by the alpha");
+            }
+        }
+    }
+}
+
+fn pixmap(size: (u32, u32)) -> Result<Pixmap> {
+    Pixmap::new(size.0.max(1), size.1.max(1)).ok_or(anyhow!("{size:?} is too large to render"))
+}
+
+fn invertible(transform: Affine) -> Option<Affine> {
+    (transform.determinant().abs() > f64::EPSILON).then(|| transform.inverse())
+}
+
+// Approximation of the error function, good to about 1e-3, plenty for 8-bit
+// channels.
+fn erf(x: f64) -> f64 {
+    let xx = x * x;
+    let x = x + (0.24295 + (0.03395 + 0.0104 * xx) * xx) * (x * xx);
+    x / (1.0 + x * x).sqrt()
+}
+
+fn transform_of(affine: Affine) -> Transform {
+    let [a, b, c, d, e, f] = affine.as_coeffs().map(|coefficient| coefficient as f32);
+    Transform::from_row(a, b, c, d, e, f)
+}
+
+fn path_of(path: &BezPath) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for element in path.elements() {
+        match *element {
+            PathEl::MoveTo(p) => builder.move_to(p.x as f32, p.y as f32),
+            PathEl::LineTo(p) => builder.line_to(p.x as f32, p.y as f32),
+            PathEl::QuadTo(p1, p) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p.x as f32, p.y as f32)
+            }
+            PathEl::CurveTo(p1, p2, p) => builder.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p.x as f32,
+                p.y as f32,
+            ),
+            PathEl::ClosePath => builder.close(),
+        }
+    }
+    builder.finish()
+}
+
+// Solid colors and the linear and radial gradients of the settings. Sweep
+// gradients and images aren't drawn by the shield, they're left out.
+fn paint_of(brush: &Brush) -> Option<Paint<'static>> {
+    let shader = match brush {
+        Brush::Solid(color) => Shader::SolidColor(color_of(*color)?),
+        Brush::Gradient(gradient) => {
+            let stops = gradient
+                .stops
+                .iter()
+                .map(|stop| {
+                    let color = color_of(stop.color.to_alpha_color::<Srgb>())?;
+                    Some(GradientStop::new(stop.offset, color))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let mode = match gradient.extend {
+                Extend::Pad => SpreadMode::Pad,
+                Extend::Repeat => SpreadMode::Repeat,
+                Extend::Reflect => SpreadMode::Reflect,
+            };
+            match gradient.kind {
+                GradientKind::Linear { start, end } => LinearGradient::new(
+                    point_of(start),
+                    point_of(end),
+                    stops,
+                    mode,
+                    Transform::identity(),
+                )?,
+                GradientKind::Radial {
+                    start_center,
+                    end_center,
+                    end_radius,
+                    ..
+                } => RadialGradient::new(
+                    point_of(start_center),
+                    point_of(end_center),
+                    end_radius,
+                    stops,
+                    mode,
+                    Transform::identity(),
+                )?,
+                GradientKind::Sweep { .. } => return None,
+            }
+        }
+        Brush::Image(_) => return None,
+    };
+
+    Some(Paint {
+        shader,
+        ..Default::default()
+    })
+}
+
+fn color_of(color: peniko::Color) -> Option<tiny_skia::Color> {
+    let [red, green, blue, alpha] = color.components;
+    tiny_skia::Color::from_rgba(
+        red.clamp(0.0, 1.0),
+        green.clamp(0.0, 1.0),
+        blue.clamp(0.0, 1.0),
+        alpha.clamp(0.0, 1.0),
+    )
+}
+
+fn point_of(point: vello::kurbo::Point) -> Point {
+    Point::from_xy(point.x as f32, point.y as f32)
+}
+
+// Outlines of glyphs are in pixels with y going up, the glyph transform applies
+// to them before they're flipped to the y down of the drawing.
+struct Pen(BezPath);
+
+impl OutlinePen for Pen {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to((x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to((x, y));
+    }
+
+    fn quad_to(&mut self, cx0: f32, cy0: f32, x: f32, y: f32) {
+        self.0.quad_to((cx0, cy0), (x, y));
+    }
+
+    fn curve_to(&mut self, cx0: f32, cy0: f32, cx1: f32, cy1: f32, x: f32, y: f32) {
+        self.0.curve_to((cx0, cy0), (cx1, cy1), (x, y));
+    }
+
+    fn close(&mut self) {
+        self.0.close_path();
+    }
+}
+
+pub fn write_png(path: &Path, size: (u32, u32), pixels: &[u8]) -> Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vello::peniko::color::palette;
+
+    #[test]
+    fn drawings_are_scaled_to_physical_pixels() {
+        let mut drawing = Drawing::default();
+        drawing.fill(
+            Affine::IDENTITY,
+            palette::css::WHITE,
+            &Rect::new(0.0, 0.0, 10.0, 10.0),
+        );
+
+        let mut offscreen = Offscreen::new((40, 40)).unwrap();
+        offscreen.render(&drawing, 2.0);
+        let alpha = |x: usize, y: usize| offscreen.premultiplied()[(y * 40 + x) * 4 + 3];
+
+        assert_eq!(alpha(15, 15), 255);
+        assert_eq!(alpha(25, 25), 0);
+    }
+}
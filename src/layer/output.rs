@@ -1,4 +1,5 @@
 use super::Layer;
+use crate::config::Output;
 use smithay_client_toolkit::delegate_output;
 use smithay_client_toolkit::output::{OutputHandler, OutputState};
 use wayland_client::protocol::wl_output;
@@ -9,12 +10,29 @@ impl OutputHandler for Layer {
         &mut self.output_state
     }
 
+    // Outputs plugged after the layer started get a shield when they're wanted:
+    // all of them, or the named one which replaces the surface the compositor placed.
     fn new_output(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
     ) {
+        let wanted = match self.settings.output() {
+            Output::All => true,
+            Output::Named(name) => self.find_output(name).as_ref() == Some(&output),
+            Output::Focused => false,
+        };
+        if !wanted {
+            return;
+        }
+
+        if !matches!(self.settings.output(), Output::All) {
+            self.targets.retain(|target| target.output().is_some());
+        }
+        if let Err(err) = self.show_on(Some(output), qh) {
+            eprintln!("Can't show the shield on the new output: {err:?}");
+        }
     }
 
     fn update_output(
@@ -25,12 +43,24 @@ impl OutputHandler for Layer {
     ) {
     }
 
+    // The compositor picks an output again when the one the shield was pinned to
+    // goes away.
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
     ) {
+        self.targets
+            .retain(|target| target.output() != Some(&output));
+        self.closed
+            .retain(|closed| closed.as_ref() != Some(&output));
+
+        if self.targets.is_empty() && !matches!(self.settings.output(), Output::All) {
+            if let Err(err) = self.show_on(None, qh) {
+                eprintln!("Can't show the shield: {err:?}");
+            }
+        }
     }
 }
 
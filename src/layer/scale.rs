@@ -0,0 +1,114 @@
+use super::Layer;
+use super::gpu::Event;
+use smithay_client_toolkit::reexports::protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+use smithay_client_toolkit::reexports::protocols::wp::viewporter::client::{
+    wp_viewport::WpViewport, wp_viewporter::WpViewporter,
+};
+use wayland_client::globals::GlobalList;
+use wayland_client::protocol::wl_surface;
+use wayland_client::{Connection, Dispatch, QueueHandle, delegate_noop};
+
+// Globals needed for fractional scaling, bound when the compositor has both.
+// With fractional scaling, the buffers are rendered at the physical size and
+// the viewport maps them back to the logical size of the surface. Without it,
+// the integer scale reported by the compositor is used as the buffer scale of
+// the surface.
+pub struct Globals {
+    fractional: Option<(WpViewporter, WpFractionalScaleManagerV1)>,
+}
+
+impl Globals {
+    pub fn bind(global: &GlobalList, qh: &QueueHandle<Layer>) -> Globals {
+        let viewporter: Option<WpViewporter> = global.bind(qh, 1..=1, ()).ok();
+        let manager: Option<WpFractionalScaleManagerV1> = global.bind(qh, 1..=1, ()).ok();
+
+        Globals {
+            fractional: viewporter.zip(manager),
+        }
+    }
+
+    // The size is the logical size of the surface.
+    pub fn attach(
+        &self,
+        surface: &wl_surface::WlSurface,
+        size: (u32, u32),
+        qh: &QueueHandle<Layer>,
+    ) -> Scaling {
+        let Some((viewporter, manager)) = &self.fractional else {
+            return Scaling::default();
+        };
+
+        let viewport = viewporter.get_viewport(surface, qh, ());
+        viewport.set_destination(size.0 as i32, size.1 as i32);
+
+        Scaling {
+            viewport: Some(viewport),
+            fractional_scale: Some(manager.get_fractional_scale(surface, qh, ())),
+        }
+    }
+}
+
+// Scaling objects of a single surface.
+#[derive(Default)]
+pub struct Scaling {
+    viewport: Option<WpViewport>,
+    fractional_scale: Option<WpFractionalScaleV1>,
+}
+
+impl Scaling {
+    pub fn is_fractional(&self) -> bool {
+        self.fractional_scale.is_some()
+    }
+
+    // The size is the logical size of the surface.
+    pub fn resize(&self, size: (u32, u32)) {
+        if let Some(viewport) = self.viewport.as_ref() {
+            viewport.set_destination(size.0 as i32, size.1 as i32);
+        }
+    }
+
+    pub fn owns(&self, fractional_scale: &WpFractionalScaleV1) -> bool {
+        self.fractional_scale.as_ref() == Some(fractional_scale)
+    }
+}
+
+impl Drop for Scaling {
+    fn drop(&mut self) {
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for Layer {
+    fn event(
+        state: &mut Self,
+        proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // The scale is sent as a numerator over a denominator of 120.
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            if let Some(target) = state
+                .targets
+                .iter()
+                .find(|target| target.scaling().owns(proxy))
+            {
+                target.send(Event::Scale(f64::from(scale) / 120.0));
+            }
+        }
+    }
+}
+
+delegate_noop!(Layer: WpViewporter);
+delegate_noop!(Layer: WpViewport);
+delegate_noop!(Layer: WpFractionalScaleManagerV1);
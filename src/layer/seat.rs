@@ -0,0 +1,109 @@
+use super::Layer;
+use crate::app;
+use smithay_client_toolkit::seat::pointer::{
+    AxisScroll, BTN_LEFT, PointerData, PointerEvent, PointerEventKind, PointerHandler,
+};
+use smithay_client_toolkit::seat::{Capability, SeatHandler, SeatState};
+use smithay_client_toolkit::{delegate_pointer, delegate_seat};
+use wayland_client::protocol::{wl_pointer, wl_seat};
+use wayland_client::{Connection, Proxy, QueueHandle};
+
+// Change of the volume for one notch of a mouse wheel.
+const SCROLL_STEP: f32 = 0.05;
+// Distance in pixels that counts as one notch when scrolling with a touchpad.
+const SCROLL_DISTANCE: f64 = 15.0;
+
+impl SeatHandler for Layer {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {}
+
+    // The pointer is taken whether the shield is interactive or not, so turning
+    // it on with a reload works. The input region of the surface is empty while
+    // it isn't, the pointer never enters the surface then.
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability != Capability::Pointer {
+            return;
+        }
+
+        match self.seat_state.get_pointer(qh, &seat) {
+            Ok(pointer) => self.pointers.push(pointer),
+            Err(err) => eprintln!("Pointer can't be used: {err:?}"),
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability != Capability::Pointer {
+            return;
+        }
+
+        self.pointers.retain(|pointer| {
+            let owned = pointer
+                .data::<PointerData>()
+                .is_some_and(|data| data.seat() == &seat);
+            if owned {
+                pointer.release();
+            }
+            !owned
+        });
+    }
+
+    fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: wl_seat::WlSeat) {
+    }
+}
+
+impl PointerHandler for Layer {
+    fn pointer_frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            if self.target_for(&event.surface).is_none() {
+                continue;
+            }
+
+            match event.kind {
+                PointerEventKind::Press { button, .. } if button == BTN_LEFT => {
+                    let _ = self.app.send(app::Event::Hide);
+                }
+                PointerEventKind::Axis { vertical, .. } => {
+                    let delta = scroll_delta(vertical);
+                    if delta != 0.0 {
+                        let _ = self.app.send(app::Event::AdjustVolume(delta));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Scrolling down lowers the volume.
+fn scroll_delta(scroll: AxisScroll) -> f32 {
+    let notches = match scroll.discrete {
+        0 => scroll.absolute / SCROLL_DISTANCE,
+        discrete => f64::from(discrete),
+    };
+
+    -(notches as f32) * SCROLL_STEP
+}
+
+delegate_seat!(Layer);
+delegate_pointer!(Layer);
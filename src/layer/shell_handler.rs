@@ -1,4 +1,6 @@
 use super::Layer;
+use super::gpu::Event;
+use smithay_client_toolkit::shell::WaylandSurface;
 use smithay_client_toolkit::shell::wlr_layer::{
     LayerShellHandler, LayerSurface, LayerSurfaceConfigure,
 };
@@ -7,19 +9,47 @@ use smithay_client_toolkit::delegate_layer;
 use wayland_client::{Connection, QueueHandle};
 
 impl LayerShellHandler for Layer {
-    // Unfortunately, I don't know how to trigger this with Dispatch and Wgpu as the
-    // driver for the surface.
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {}
+    // The compositor destroyed the surface, the engine is stopped so it doesn't
+    // present to it anymore. The target is created again right away while the
+    // shield is shown, otherwise the next time it is.
+    fn closed(&mut self, _conn: &Connection, qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        let Some(index) = self
+            .targets
+            .iter()
+            .position(|target| target.has_surface(layer.wl_surface()))
+        else {
+            return;
+        };
 
+        let output = self.targets.remove(index).output().cloned();
+        if self.hidden {
+            self.closed.push(output);
+        } else if let Err(err) = self.show_on(output, qh) {
+            eprintln!("Can't recreate the surface closed by the compositor: {err:?}");
+        }
+    }
+
+    // A size of 0 leaves the dimension to the client, which is the size from the settings.
     fn configure(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
-        _configure: LayerSurfaceConfigure,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
-        self.sender.send(crate::layer::gpu::Event::Configure);
+        let (width, height) = self.settings.surface_size();
+        let size = match configure.new_size {
+            (0, 0) => (width, height),
+            (0, h) => (width, h),
+            (w, 0) => (w, height),
+            size => size,
+        };
+
+        if let Some(target) = self.target_for(layer.wl_surface()) {
+            target.scaling().resize(size);
+            target.send(Event::Configure(size.0, size.1));
+        }
     }
 }
 
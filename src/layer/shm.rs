@@ -0,0 +1,89 @@
+use crate::config::Settings;
+use anyhow::Result;
+use smithay_client_toolkit::shm::Shm;
+use smithay_client_toolkit::shm::slot::{Buffer, SlotPool};
+use std::sync::Arc;
+use wayland_client::Connection;
+use wayland_client::protocol::{wl_shm, wl_surface};
+
+use super::engine::{self, Engine};
+use super::offscreen::Offscreen;
+use super::surface::Visibility;
+use crate::shield::Drawing;
+
+// Backend used when no GPU adapter can present to the surface. The drawing is
+// rasterized on the CPU and the pixels are copied into a wl_shm buffer that is
+// attached to the surface.
+pub struct Backend {
+    connection: Connection,
+    surface: wl_surface::WlSurface,
+    visibility: Visibility,
+    pool: SlotPool,
+    offscreen: Offscreen,
+    // The buffer attached to the surface is kept until the next one replaces it.
+    buffer: Option<Buffer>,
+}
+
+pub fn create(
+    settings: &Arc<Settings>,
+    connection: &Connection,
+    surface: wl_surface::WlSurface,
+    shm: &Shm,
+) -> Result<Engine<Backend>> {
+    let size = settings.surface_size();
+    let offscreen = Offscreen::new(size)?;
+    // Room for two buffers: the one on screen and the one being drawn.
+    let pool = SlotPool::new((size.0 * size.1 * 4 * 2) as usize, shm)?;
+
+    let backend = Backend {
+        connection: connection.clone(),
+        visibility: Visibility::new(connection, surface.clone()),
+        surface,
+        pool,
+        offscreen,
+        buffer: None,
+    };
+    Ok(Engine::new(backend, size))
+}
+
+impl engine::Backend for Backend {
+    fn visibility(&mut self) -> &mut Visibility {
+        &mut self.visibility
+    }
+
+    fn resize(&mut self, size: (u32, u32)) -> Result<()> {
+        self.offscreen.resize(size)
+    }
+
+    fn render(&mut self, drawing: &Drawing, scale: f64) -> Result<()> {
+        self.offscreen.render(drawing, scale);
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<()> {
+        let (width, height) = self.offscreen.size();
+        let (width, height) = (width as i32, height as i32);
+        let (buffer, canvas) =
+            self.pool
+                .create_buffer(width, height, width * 4, wl_shm::Format::Argb8888)?;
+
+        // The offscreen pixels are premultiplied RGBA while wl_shm's ARGB8888 is
+        // little-endian, so the bytes are laid out as BGRA.
+        let pixels = self.offscreen.premultiplied();
+        for (dst, src) in canvas.chunks_exact_mut(4).zip(pixels.chunks_exact(4)) {
+            dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+        }
+
+        buffer.attach_to(&self.surface)?;
+        self.surface.damage_buffer(0, 0, width, height);
+        self.surface.commit();
+        self.connection.flush()?;
+        self.buffer = Some(buffer);
+
+        Ok(())
+    }
+
+    fn hidden(&mut self) {
+        self.buffer = None;
+    }
+}
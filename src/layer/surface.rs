@@ -1,36 +1,31 @@
 use super::Layer;
-use crate::config::Settings;
+use crate::config::{Edge, Settings, Stacking};
 use crate::layer::wgpu::Surface;
 use anyhow::{Ok, Result, anyhow};
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
-use smithay_client_toolkit::compositor::CompositorState;
+use smithay_client_toolkit::compositor::{CompositorState, Region};
 use smithay_client_toolkit::shell::WaylandSurface;
 use smithay_client_toolkit::shell::wlr_layer::{
     self, Anchor, KeyboardInteractivity, LayerShell, LayerSurface,
 };
 use std::ptr::NonNull;
 use wayland_client::Proxy;
+use wayland_client::protocol::{wl_output, wl_surface};
 use wayland_client::{Connection, QueueHandle};
 use wgpu::Instance;
 
 #[derive(Default)]
 pub struct Builder<'a> {
-    connection: Option<&'a Connection>,
     compositor: Option<&'a CompositorState>,
     queue_handle: Option<&'a QueueHandle<Layer>>,
     layer_shell: Option<&'a LayerShell>,
     settings: Option<&'a Settings>,
-    instance: Option<&'a Instance>,
+    output: Option<&'a wl_output::WlOutput>,
 }
 
 impl<'a> Builder<'a> {
-    pub fn with_connection(&'a mut self, conn: &'a Connection) -> &'a mut Self {
-        self.connection = Some(conn);
-        self
-    }
-
     pub fn with_layer_shell(&'a mut self, layer_shell: &'a LayerShell) -> &'a mut Self {
         self.layer_shell = Some(layer_shell);
         self
@@ -51,53 +46,166 @@ impl<'a> Builder<'a> {
         self
     }
 
-    pub fn with_instance(&'a mut self, instance: &'a Instance) -> &'a mut Self {
-        self.instance = Some(instance);
+    // Without an output, the compositor decides where the surface goes.
+    pub fn with_output(&'a mut self, output: Option<&'a wl_output::WlOutput>) -> &'a mut Self {
+        self.output = output;
         self
     }
 
-    pub fn create<'window>(&self) -> Result<(LayerSurface, Surface<'window>)> {
+    pub fn create(&self) -> Result<LayerSurface> {
         let settings = self.settings.ok_or(anyhow!("Settings not present"))?;
         let layer_shell = self.layer_shell.ok_or(anyhow!("LayerShell is missing"))?;
         let queue_handle = self.queue_handle.ok_or(anyhow!("QueueHandle is missing"))?;
         let compositor = self.compositor.ok_or(anyhow!("Compositor is missing"))?;
-        let connection = self
-            .connection
-            .ok_or(anyhow!("Wayland connection is missing"))?;
-        let instance = self.instance.ok_or(anyhow!("GPU Instance is missing"))?;
 
         let layer_surface = layer_shell.create_layer_surface(
             queue_handle,
             compositor.create_surface(queue_handle),
-            wlr_layer::Layer::Top,
+            match settings.layer() {
+                Stacking::Top => wlr_layer::Layer::Top,
+                Stacking::Overlay => wlr_layer::Layer::Overlay,
+            },
             Some("trampoline:main"),
-            None,
+            self.output,
         );
-        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
-        layer_surface.set_anchor(Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
-        layer_surface.set_size(settings.size().0, settings.size().1);
-        layer_surface.set_margin(0, settings.position().0, settings.position().1, 0);
+        place(&layer_surface, settings, compositor)?;
 
         // Commit does so much under the hood; it binds a wl_buffer and hooks into
         // Wayland server which means the LayerSurface can be dropped by this function and
         // it will leave on.
         layer_surface.commit();
 
-        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(
-            NonNull::new(connection.backend().display_ptr() as *mut _).unwrap(),
-        ));
-        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(
-            NonNull::new(layer_surface.wl_surface().id().as_ptr() as *mut _).unwrap(),
-        ));
-
-        let raw = unsafe {
-            instance
-                .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
-                    raw_display_handle,
-                    raw_window_handle,
-                })
-                .unwrap()
-        };
-        Ok((layer_surface, raw))
+        Ok(layer_surface)
+    }
+}
+
+// Applies the settings that shape the layer surface. It's done on creation and
+// again when the settings are reloaded, the changes take effect on the next commit.
+pub fn place(
+    layer_surface: &LayerSurface,
+    settings: &Settings,
+    compositor: &CompositorState,
+) -> Result<()> {
+    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+    layer_surface.set_layer(match settings.layer() {
+        Stacking::Top => wlr_layer::Layer::Top,
+        Stacking::Overlay => wlr_layer::Layer::Overlay,
+    });
+    // The input region is copied by the compositor, the region can be dropped right away.
+    let region = Region::new(compositor)?;
+    if *settings.behavior().interactive() {
+        let padding = settings.padding() as i32;
+        let (width, height) = *settings.size();
+        region.add(padding, padding, width as i32, height as i32);
+    }
+    layer_surface.set_input_region(Some(region.wl_region()));
+    let (anchor, margin) = placement(settings);
+    layer_surface.set_anchor(anchor);
+    let (width, height) = settings.surface_size();
+    layer_surface.set_size(width, height);
+    layer_surface.set_margin(margin.0, margin.1, margin.2, margin.3);
+
+    Ok(())
+}
+
+// Anchors the surface to the edges of the anchor and turns the position into
+// margins from those edges (top, right, bottom, left). A centered axis is anchored
+// on both sides. The surface is larger than the shield when it has a shadow, the
+// margins are reduced by the padding so the shield itself stays at its position.
+//
+// Horizontally, a centered shield keeps x as its margin from the right edge and
+// is centered in the rest of the output, the way it was placed before anchors
+// could be configured. A vertically centered shield ignores y, which defaults
+// to the distance from the bottom edge.
+fn placement(settings: &Settings) -> (Anchor, (i32, i32, i32, i32)) {
+    let padding = settings.padding() as i32;
+    let (x, y) = (
+        settings.position().0 - padding,
+        settings.position().1 - padding,
+    );
+    let (horizontal, vertical) = settings.anchor().edges();
+    let mut anchor = Anchor::empty();
+    let mut margin = (0, 0, 0, 0);
+
+    match horizontal {
+        Edge::Start => {
+            anchor |= Anchor::LEFT;
+            margin.3 = x;
+        }
+        Edge::Center => {
+            anchor |= Anchor::LEFT | Anchor::RIGHT;
+            margin.1 = settings.position().0;
+        }
+        Edge::End => {
+            anchor |= Anchor::RIGHT;
+            margin.1 = x;
+        }
+    }
+
+    match vertical {
+        Edge::Start => {
+            anchor |= Anchor::TOP;
+            margin.0 = y;
+        }
+        Edge::Center => anchor |= Anchor::TOP | Anchor::BOTTOM,
+        Edge::End => {
+            anchor |= Anchor::BOTTOM;
+            margin.2 = y;
+        }
     }
+
+    (anchor, margin)
+}
+
+// Maps and unmaps the layer surface from the thread of an engine. Attaching no
+// buffer unmaps the surface. To map it again, the surface is committed without
+// a buffer and the next buffer can only be attached after the compositor sent a
+// new configure.
+pub struct Visibility {
+    connection: Connection,
+    surface: wl_surface::WlSurface,
+}
+
+impl Visibility {
+    pub fn new(connection: &Connection, surface: wl_surface::WlSurface) -> Visibility {
+        Visibility {
+            connection: connection.clone(),
+            surface,
+        }
+    }
+
+    pub fn unmap(&self) -> Result<()> {
+        self.surface.attach(None, 0, 0);
+        self.surface.commit();
+        self.connection.flush()?;
+        Ok(())
+    }
+
+    pub fn remap(&self) -> Result<()> {
+        self.surface.commit();
+        self.connection.flush()?;
+        Ok(())
+    }
+}
+
+// Creates the GPU surface that presents to the layer surface.
+pub fn gpu_surface<'window>(
+    connection: &Connection,
+    layer_surface: &LayerSurface,
+    instance: &Instance,
+) -> Result<Surface<'window>> {
+    let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(
+        NonNull::new(connection.backend().display_ptr() as *mut _).unwrap(),
+    ));
+    let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(
+        NonNull::new(layer_surface.wl_surface().id().as_ptr() as *mut _).unwrap(),
+    ));
+
+    let raw = unsafe {
+        instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
+            raw_display_handle,
+            raw_window_handle,
+        })?
+    };
+    Ok(raw)
 }
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use crossbeam::channel;
+use smithay_client_toolkit::compositor::CompositorState;
+use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::shell::wlr_layer::LayerSurface;
+use std::thread::JoinHandle;
+use wayland_client::QueueHandle;
+use wayland_client::protocol::{wl_output, wl_surface};
+
+use super::gpu::{self, Event};
+use super::scale::Scaling;
+use super::{Layer, engine, shm, surface};
+use crate::config::{Renderer, Settings};
+
+// A target is a layer surface on one output along with the engine rendering to
+// it. The engine runs on its own thread and is fed through the sender. Without
+// an output, the compositor picks the output the surface is shown on.
+pub struct Target {
+    output: Option<wl_output::WlOutput>,
+    layer_surface: LayerSurface,
+    scaling: Scaling,
+    sender: channel::Sender<Event>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Target {
+    pub fn create(
+        layer: &Layer,
+        output: Option<wl_output::WlOutput>,
+        qh: &QueueHandle<Layer>,
+    ) -> Result<Target> {
+        let layer_surface = surface::Builder::default()
+            .with_layer_shell(&layer.layer_shell)
+            .with_compositor(&layer.compositor)
+            .with_queue_handle(qh)
+            .with_settings(&layer.settings)
+            .with_output(output.as_ref())
+            .create()?;
+        let scaling = layer.scale.attach(
+            layer_surface.wl_surface(),
+            layer.settings.surface_size(),
+            qh,
+        );
+
+        let mut engine = match layer.settings.renderer() {
+            Renderer::Gpu => Engine::Gpu(gpu_engine(layer, &layer_surface)?),
+            Renderer::Cpu => Engine::Cpu(cpu_engine(layer, &layer_surface)?),
+            Renderer::Auto => match gpu_engine(layer, &layer_surface) {
+                Ok(engine) => Engine::Gpu(engine),
+                Err(err) => {
+                    eprintln!("Falling back to the CPU renderer: {err:?}");
+                    Engine::Cpu(cpu_engine(layer, &layer_surface)?)
+                }
+            },
+        };
+
+        let (sender, mut receiver) = channel::unbounded();
+        let handle = std::thread::spawn(move || {
+            if let Err(err) = engine.ingest(&mut receiver) {
+                eprintln!("Engine stopped: {err:?}");
+            }
+        });
+
+        Ok(Target {
+            output,
+            layer_surface,
+            scaling,
+            sender,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn output(&self) -> Option<&wl_output::WlOutput> {
+        self.output.as_ref()
+    }
+
+    pub fn scaling(&self) -> &Scaling {
+        &self.scaling
+    }
+
+    pub fn has_surface(&self, surface: &wl_surface::WlSurface) -> bool {
+        self.layer_surface.wl_surface() == surface
+    }
+
+    // Shapes the surface after the settings, the compositor answers with a
+    // configure that resizes the engine.
+    pub fn place(&self, settings: &Settings, compositor: &CompositorState) -> Result<()> {
+        surface::place(&self.layer_surface, settings, compositor)?;
+        self.layer_surface.commit();
+        Ok(())
+    }
+
+    pub fn send(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+}
+
+// The engine is stopped before the layer surface is dropped so the GPU surface
+// never outlives the Wayland surface it presents to.
+impl Drop for Target {
+    fn drop(&mut self) {
+        self.send(Event::Terminate);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn gpu_engine(
+    layer: &Layer,
+    layer_surface: &LayerSurface,
+) -> Result<engine::Engine<gpu::Backend<'static>>> {
+    let instance = gpu::get_instance();
+    let surface = surface::gpu_surface(&layer.connection, layer_surface, &instance)?;
+    let visibility =
+        surface::Visibility::new(&layer.connection, layer_surface.wl_surface().clone());
+    gpu::create(&layer.settings, surface, visibility, instance)
+}
+
+fn cpu_engine(layer: &Layer, layer_surface: &LayerSurface) -> Result<engine::Engine<shm::Backend>> {
+    shm::create(
+        &layer.settings,
+        &layer.connection,
+        layer_surface.wl_surface().clone(),
+        &layer.shm,
+    )
+}
+
+// Both engines consume the same events. The CPU engine is used when the GPU
+// can't present to the surface, or when it's requested in the settings.
+enum Engine {
+    Gpu(engine::Engine<gpu::Backend<'static>>),
+    Cpu(engine::Engine<shm::Backend>),
+}
+
+impl Engine {
+    fn ingest(&mut self, receiver: &mut channel::Receiver<Event>) -> Result<()> {
+        match self {
+            Engine::Gpu(engine) => engine.ingest(receiver),
+            Engine::Cpu(engine) => engine.ingest(receiver),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+// Headless compositor used by the tests of the layer. It only implements what
+// the layer binds: wl_compositor, wl_shm and wlr-layer-shell. Nothing is drawn,
+// the state of every layer surface and the buffers committed to it are recorded
+// so the tests can look at them.
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use smithay::reexports::calloop::generic::Generic;
+use smithay::reexports::calloop::{EventLoop, Interest, Mode, PostAction, channel};
+use smithay::reexports::wayland_server::backend::{ClientData, ClientId, DisconnectReason};
+use smithay::reexports::wayland_server::protocol::{wl_buffer, wl_output, wl_surface};
+use smithay::reexports::wayland_server::{Client, Display, DisplayHandle};
+use smithay::wayland::buffer::BufferHandler;
+use smithay::wayland::compositor::{
+    BufferAssignment, CompositorClientState, CompositorHandler, CompositorState, SurfaceAttributes,
+    with_states,
+};
+use smithay::wayland::shell::wlr_layer::{
+    self, LayerSurfaceCachedState, LayerSurfaceData, WlrLayerShellHandler, WlrLayerShellState,
+};
+use smithay::wayland::shm::{ShmHandler, ShmState, with_buffer_contents};
+use smithay::{delegate_compositor, delegate_layer_shell, delegate_shm};
+use wayland_client::Connection;
+
+// How long the tests wait for the client to do something before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Pixels of a wl_shm buffer, copied when it was committed.
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    pub size: (i32, i32),
+    pub stride: i32,
+    pub pixels: Vec<u8>,
+}
+
+// State of a layer surface as of its last commit.
+#[derive(Debug, Clone)]
+pub struct Surface {
+    pub namespace: String,
+    pub layer: wlr_layer::Layer,
+    pub anchor: wlr_layer::Anchor,
+    pub margin: wlr_layer::Margins,
+    pub size: (i32, i32),
+    pub buffers: Vec<Buffer>,
+    pub commits: usize,
+    // Set when a commit removed the buffer, cleared when a buffer is attached again.
+    pub unmapped: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Record {
+    pub surfaces: Vec<Surface>,
+}
+
+// The record is shared with the tests, which are woken up every time it changes.
+#[derive(Default)]
+struct Shared {
+    record: Mutex<Record>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn update(&self, update: impl FnOnce(&mut Record)) {
+        update(&mut self.record.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+pub struct Compositor {
+    shared: Arc<Shared>,
+    // Dropping the sender stops the compositor.
+    stop: Option<channel::Sender<()>>,
+    // Indices of the surfaces in the record to close, like when their output
+    // goes away.
+    close: channel::Sender<usize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Compositor {
+    // Starts the compositor on its own thread and returns the connection of
+    // the only client it accepts.
+    pub fn start() -> (Compositor, Connection) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let shared = Arc::new(Shared::default());
+        let (stop, stopped) = channel::channel();
+        let (close, closing) = channel::channel();
+
+        let handle = {
+            let shared = shared.clone();
+            std::thread::spawn(move || serve(server, shared, stopped, closing))
+        };
+
+        let compositor = Compositor {
+            shared,
+            stop: Some(stop),
+            close,
+            handle: Some(handle),
+        };
+
+        (compositor, Connection::from_socket(client).unwrap())
+    }
+
+    // Waits until the record satisfies the predicate and returns what the
+    // predicate extracted from it.
+    pub fn wait_for<T>(&self, predicate: impl Fn(&Record) -> Option<T>) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut record = self.shared.record.lock().unwrap();
+        loop {
+            if let Some(value) = predicate(&record) {
+                return value;
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            assert!(
+                !left.is_zero(),
+                "compositor state never matched: {record:?}"
+            );
+            record = self.shared.changed.wait_timeout(record, left).unwrap().0;
+        }
+    }
+
+    // Tells the client its surface was closed, the surface is at the given
+    // index of the record.
+    pub fn close(&self, index: usize) {
+        self.close.send(index).unwrap();
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// The loop sleeps until the client sends requests or the compositor is stopped.
+fn serve(
+    socket: UnixStream,
+    shared: Arc<Shared>,
+    stopped: channel::Channel<()>,
+    closing: channel::Channel<usize>,
+) {
+    let display: Display<State> = Display::new().unwrap();
+    let handle = display.handle();
+    let mut state = State {
+        compositor: CompositorState::new::<State>(&handle),
+        shm: ShmState::new::<State>(&handle, vec![]),
+        layer_shell: WlrLayerShellState::new::<State>(&handle),
+        surfaces: vec![],
+        shared,
+        display: handle.clone(),
+    };
+
+    handle
+        .insert_client(socket, Arc::new(ClientState::default()))
+        .unwrap();
+
+    let mut event_loop: EventLoop<State> = EventLoop::try_new().unwrap();
+    let signal = event_loop.get_signal();
+    let loop_handle = event_loop.handle();
+    loop_handle
+        .insert_source(
+            Generic::new(display, Interest::READ, Mode::Level),
+            |_, display, state| {
+                // Safety: the display is only dropped along with the source.
+                unsafe { display.get_mut().dispatch_clients(state).unwrap() };
+                Ok(PostAction::Continue)
+            },
+        )
+        .unwrap();
+    loop_handle
+        .insert_source(stopped, move |_, _, _| signal.stop())
+        .unwrap();
+    loop_handle
+        .insert_source(closing, |event, _, state| {
+            if let channel::Event::Msg(index) = event {
+                if let Some((layer_surface, _)) =
+                    state.surfaces.iter().find(|(_, entry)| *entry == index)
+                {
+                    layer_surface.send_close();
+                }
+            }
+        })
+        .unwrap();
+
+    event_loop
+        .run(None, &mut state, |state| {
+            state.display.flush_clients().unwrap()
+        })
+        .unwrap();
+}
+
+struct State {
+    compositor: CompositorState,
+    shm: ShmState,
+    layer_shell: WlrLayerShellState,
+    // Layer surfaces with the index of their entry in the record.
+    surfaces: Vec<(wlr_layer::LayerSurface, usize)>,
+    shared: Arc<Shared>,
+    display: DisplayHandle,
+}
+
+impl State {
+    // The size requested by the client is given back as is in the configure,
+    // like compositors do when the surface fits on the output.
+    fn commit_layer_surface(&mut self, surface: &wl_surface::WlSurface, index: usize) {
+        let (layer, anchor, margin, size) = with_states(surface, |states| {
+            let mut cached = states.cached_state.get::<LayerSurfaceCachedState>();
+            let current = cached.current();
+            (
+                current.layer,
+                current.anchor,
+                current.margin,
+                (current.size.w, current.size.h),
+            )
+        });
+        let assignment = with_states(surface, |states| {
+            states
+                .cached_state
+                .get::<SurfaceAttributes>()
+                .current()
+                .buffer
+                .take()
+        });
+
+        let mut remapped = false;
+        self.shared.update(|record| {
+            let entry = &mut record.surfaces[index];
+            // A commit without a buffer on an unmapped surface maps it again,
+            // which has to be answered with a configure.
+            remapped = assignment.is_none() && entry.unmapped;
+            entry.layer = layer;
+            entry.anchor = anchor;
+            entry.margin = margin;
+            entry.size = size;
+            entry.commits += 1;
+
+            match assignment {
+                Some(BufferAssignment::NewBuffer(buffer)) => {
+                    if let Some(copy) = copy_buffer(&buffer) {
+                        entry.buffers.push(copy);
+                    }
+                    entry.unmapped = false;
+                    buffer.release();
+                }
+                Some(BufferAssignment::Removed) => entry.unmapped = true,
+                None => {}
+            }
+        });
+
+        let configured = with_states(surface, |states| {
+            states
+                .data_map
+                .get::<LayerSurfaceData>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .initial_configure_sent
+        });
+        if !configured || remapped {
+            let (layer_surface, _) = &self.surfaces[index];
+            layer_surface.with_pending_state(|state| state.size = Some(size.into()));
+            layer_surface.send_configure();
+        }
+    }
+}
+
+fn copy_buffer(buffer: &wl_buffer::WlBuffer) -> Option<Buffer> {
+    with_buffer_contents(buffer, |ptr, len, data| {
+        let pixels = unsafe { std::slice::from_raw_parts(ptr, len) };
+        let start = data.offset as usize;
+        let end = start + (data.stride * data.height) as usize;
+        Buffer {
+            size: (data.width, data.height),
+            stride: data.stride,
+            pixels: pixels[start..end].to_vec(),
+        }
+    })
+    .ok()
+}
+
+#[derive(Default)]
+struct ClientState {
+    compositor: CompositorClientState,
+}
+
+impl ClientData for ClientState {
+    fn initialized(&self, _client_id: ClientId) {}
+    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
+}
+
+impl CompositorHandler for State {
+    fn compositor_state(&mut self) -> &mut CompositorState {
+        &mut self.compositor
+    }
+
+    fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
+        &client.get_data::<ClientState>().unwrap().compositor
+    }
+
+    fn commit(&mut self, surface: &wl_surface::WlSurface) {
+        let index = self
+            .surfaces
+            .iter()
+            .find(|(layer_surface, _)| layer_surface.wl_surface() == surface)
+            .map(|(_, index)| *index);
+
+        if let Some(index) = index {
+            self.commit_layer_surface(surface, index);
+        }
+    }
+}
+
+impl BufferHandler for State {
+    fn buffer_destroyed(&mut self, _buffer: &wl_buffer::WlBuffer) {}
+}
+
+impl ShmHandler for State {
+    fn shm_state(&self) -> &ShmState {
+        &self.shm
+    }
+}
+
+impl WlrLayerShellHandler for State {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        surface: wlr_layer::LayerSurface,
+        _output: Option<wl_output::WlOutput>,
+        layer: wlr_layer::Layer,
+        namespace: String,
+    ) {
+        let mut index = 0;
+        self.shared.update(|record| {
+            record.surfaces.push(Surface {
+                namespace,
+                layer,
+                anchor: wlr_layer::Anchor::empty(),
+                margin: wlr_layer::Margins::default(),
+                size: (0, 0),
+                buffers: vec![],
+                commits: 0,
+                unmapped: false,
+            });
+            index = record.surfaces.len() - 1;
+        });
+        self.surfaces.push((surface, index));
+    }
+}
+
+delegate_compositor!(State);
+delegate_shm!(State);
+delegate_layer_shell!(State);
@@ -0,0 +1,210 @@
+// The layer is started against the headless compositor of compositor.rs, the
+// CPU renderer is used so the buffers it commits can be read back.
+use std::sync::Arc;
+
+use smithay::wayland::shell::wlr_layer as server;
+
+use super::{Layer, Wire, gpu::Event};
+use crate::config::Settings;
+use crate::shield::Shield;
+
+mod compositor;
+use compositor::Compositor;
+
+fn start(toml: &str) -> (Compositor, Wire, Arc<Settings>) {
+    let settings = Arc::new(Settings::from_toml(&format!("renderer = \"cpu\"\n{toml}")).unwrap());
+    let (compositor, connection) = Compositor::start();
+    let (app, _) = crossbeam::channel::unbounded();
+
+    let layer = Layer::with_connection(connection, settings.clone(), app).unwrap();
+    let wire = layer.run().unwrap();
+
+    (compositor, wire, settings)
+}
+
+fn committed(record: &compositor::Record) -> Option<compositor::Surface> {
+    record
+        .surfaces
+        .first()
+        .filter(|surface| surface.commits > 0)
+        .cloned()
+}
+
+fn stop(wire: Wire) {
+    wire.sender.send(Event::Terminate).unwrap();
+    wire.handle.join().unwrap();
+}
+
+#[test]
+fn surface_follows_the_settings() {
+    let (compositor, wire, settings) = start(
+        "[frame]\nanchor = \"top-right\"\nlayer = \"overlay\"\n[frame.position]\nx = 20\ny = 30\n",
+    );
+
+    let surface = compositor.wait_for(committed);
+    let padding = settings.padding() as i32;
+    let (width, height) = settings.surface_size();
+
+    assert_eq!(surface.namespace, "trampoline:main");
+    assert_eq!(surface.layer, server::Layer::Overlay);
+    assert_eq!(surface.anchor, server::Anchor::TOP | server::Anchor::RIGHT);
+    assert_eq!(
+        (surface.margin.top, surface.margin.right),
+        (30 - padding, 20 - padding)
+    );
+    assert_eq!((surface.margin.bottom, surface.margin.left), (0, 0));
+    assert_eq!(surface.size, (width as i32, height as i32));
+
+    stop(wire);
+}
+
+#[test]
+fn default_surface_is_centered_at_the_bottom() {
+    let (compositor, wire, settings) = start("");
+
+    let surface = compositor.wait_for(committed);
+    let padding = settings.padding() as i32;
+
+    assert_eq!(surface.layer, server::Layer::Top);
+    assert_eq!(
+        surface.anchor,
+        server::Anchor::BOTTOM | server::Anchor::LEFT | server::Anchor::RIGHT
+    );
+    assert_eq!(surface.margin.bottom, settings.position().1 - padding);
+
+    stop(wire);
+}
+
+#[test]
+fn position_moves_a_centered_surface() {
+    let (compositor, wire, _) = start("[frame.position]\nx = 40\n");
+
+    let surface = compositor.wait_for(committed);
+
+    assert_eq!((surface.margin.left, surface.margin.right), (0, 40));
+
+    stop(wire);
+}
+
+#[test]
+fn cpu_engine_commits_the_scene() {
+    let (compositor, wire, settings) = start("");
+    let mut shield = Shield::new(settings.clone());
+
+    wire.sender.send(Event::Render(shield.draw(0.5))).unwrap();
+    wire.sender.send(Event::Paint).unwrap();
+
+    let buffer = compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .and_then(|surface| surface.buffers.last().cloned())
+    });
+    let (width, height) = settings.surface_size();
+
+    assert_eq!(buffer.size, (width as i32, height as i32));
+    // The center of the shield is covered by the background, ARGB8888 keeps
+    // the alpha in the last byte of every pixel.
+    let center = (buffer.size.1 / 2 * buffer.stride + buffer.size.0 / 2 * 4) as usize;
+    assert!(buffer.pixels[center + 3] > 0, "the shield wasn't drawn");
+
+    stop(wire);
+}
+
+#[test]
+fn hiding_unmaps_and_showing_maps_again() {
+    let (compositor, wire, settings) = start("");
+    let mut shield = Shield::new(settings);
+
+    wire.sender.send(Event::Render(shield.draw(0.5))).unwrap();
+    wire.sender.send(Event::Paint).unwrap();
+    compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .filter(|surface| !surface.buffers.is_empty())
+            .map(|_| ())
+    });
+
+    wire.sender.send(Event::Hide).unwrap();
+    compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .filter(|surface| surface.unmapped)
+            .map(|_| ())
+    });
+
+    // The buffer comes back once the compositor configured the surface again.
+    wire.sender.send(Event::Show).unwrap();
+    compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .filter(|surface| !surface.unmapped && surface.buffers.len() > 1)
+            .map(|_| ())
+    });
+
+    stop(wire);
+}
+
+#[test]
+fn surfaces_closed_while_shown_are_created_again() {
+    let (compositor, wire, settings) = start("");
+    let mut shield = Shield::new(settings);
+
+    wire.sender.send(Event::Render(shield.draw(0.5))).unwrap();
+    wire.sender.send(Event::Paint).unwrap();
+    compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .filter(|surface| !surface.buffers.is_empty())
+            .map(|_| ())
+    });
+
+    // The new surface shows the last drawing without waiting for another one.
+    compositor.close(0);
+    compositor.wait_for(|record| {
+        record
+            .surfaces
+            .get(1)
+            .filter(|surface| !surface.buffers.is_empty())
+            .map(|_| ())
+    });
+
+    stop(wire);
+}
+
+#[test]
+fn reloaded_settings_move_the_surface() {
+    let (compositor, wire, _) = start("");
+    compositor.wait_for(committed);
+
+    let settings = Arc::new(
+        Settings::from_toml(
+            "renderer = \"cpu\"\n[frame]\nanchor = \"top-left\"\nlayer = \"overlay\"\n\
+             [frame.position]\nx = 50\ny = 60\n",
+        )
+        .unwrap(),
+    );
+    wire.sender.send(Event::Settings(settings.clone())).unwrap();
+
+    let surface = compositor.wait_for(|record| {
+        record
+            .surfaces
+            .first()
+            .filter(|surface| surface.anchor == server::Anchor::TOP | server::Anchor::LEFT)
+            .cloned()
+    });
+    let padding = settings.padding() as i32;
+
+    assert_eq!(surface.layer, server::Layer::Overlay);
+    assert_eq!(
+        (surface.margin.top, surface.margin.left),
+        (60 - padding, 50 - padding)
+    );
+    assert_eq!((surface.margin.bottom, surface.margin.right), (0, 0));
+
+    stop(wire);
+}
@@ -1,29 +1,186 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 mod app;
 pub use app::App;
 
+mod appearance;
 mod audio;
 mod config;
+mod ipc;
 mod layer;
+mod metrics;
+mod notifications;
 mod shield;
+mod timer;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     config: Option<String>,
+
+    /// Print what the app does, like how many events were coalesced
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Refuse to start with a configuration that has problems
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Show the levels sent as desktop notifications, e.g. by OSD scripts
+    #[arg(long)]
+    notifications: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the shield off-screen and write it to a PNG file
+    Render {
+        /// Value displayed by the shield, 1.0 being 100%
+        #[arg(long, default_value_t = 0.5)]
+        value: f32,
+
+        #[arg(long, default_value = "shield.png")]
+        out: PathBuf,
+    },
+    /// Ask the running shield to read its configuration again
+    Reload,
+    /// Report every problem of the configuration without starting the shield
+    CheckConfig,
+    /// Describe the keys of the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the JSON Schema of the configuration
+    Schema,
+    /// Print the default configuration in TOML, every key commented out
+    Default,
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut settings = config::Settings::new(args.config).unwrap();
+    match args.command {
+        Some(Command::Render { value, out }) => {
+            let settings = load(args.config, args.strict, args.verbose);
+            if let Err(err) = render(settings, value, &out) {
+                eprintln!("The shield can't be rendered: {err:?}");
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Reload) => reload(),
+        Some(Command::CheckConfig) => check_config(args.config),
+        Some(Command::Config { command }) => match command {
+            ConfigCommand::Schema => println!("{}", config::json_schema()),
+            ConfigCommand::Default => print!("{}", config::default_toml()),
+        },
+        None => {
+            let settings = load(args.config, args.strict, args.verbose);
+            run(settings, args.verbose, args.strict, args.notifications)
+        }
+    }
+}
+
+fn load(path: Option<String>, strict: bool, verbose: bool) -> config::Settings {
+    let settings = match config::Settings::new(path) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("The configuration can't be read: {err}");
+            std::process::exit(1);
+        }
+    };
+    if verbose {
+        eprintln!("{}", settings.loaded());
+    }
+    if let Err(err) = settings.report(strict) {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+    settings
+}
+
+fn check_config(path: Option<String>) {
+    let settings = match config::Settings::new(path) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("The configuration can't be read: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    println!("{}", settings.loaded());
+    let problems = settings.problems();
+    for problem in problems {
+        println!("{problem}");
+    }
+
+    if problems.is_empty() {
+        println!("The configuration is valid");
+    } else {
+        println!("{} problem(s) found", problems.len());
+        std::process::exit(1);
+    }
+}
+
+// Talks to the running shield, the configuration is read by it.
+fn reload() {
+    if let Err(err) = ipc::send("reload") {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
 
+fn run(settings: config::Settings, verbose: bool, strict: bool, serve_notifications: bool) {
     let mut app = App::new(settings);
+    app.set_verbose(verbose);
+    app.set_strict(strict);
 
     let mut audio = audio::Audio::new().unwrap();
-    app.register_handle(audio.monitor(app.sender().clone()));
+    app.register_handle(audio.monitor(app.sender().clone(), app.metrics().clone()));
+    app.register_audio(audio);
+
+    app.follow_system_scheme();
+
+    match config::watch(app.settings().sources(), app.sender().clone()) {
+        Ok(watcher) => app.register_watcher(watcher),
+        Err(err) => eprintln!("Configuration files can't be watched: {err:?}"),
+    }
+    match ipc::on_hangup(app.sender().clone()) {
+        Ok(handle) => app.register_handle(handle),
+        Err(err) => eprintln!("SIGHUP can't be handled: {err:?}"),
+    }
+    match ipc::listen(app.sender().clone()) {
+        Ok(handle) => app.register_handle(handle),
+        Err(err) => eprintln!("Commands can't be received: {err:?}"),
+    }
+
+    if serve_notifications {
+        match notifications::serve(app.sender().clone()) {
+            Ok(notifications) => app.register_notifications(notifications),
+            Err(err) => eprintln!("Notifications can't be received: {err:?}"),
+        }
+    }
 
     app.start()
 }
+
+// Doesn't need a compositor nor a GPU, the shield is rasterized on the CPU.
+fn render(settings: config::Settings, value: f32, out: &Path) -> anyhow::Result<()> {
+    let settings = Arc::new(settings);
+    let size = settings.surface_size();
+    let mut shield = shield::Shield::new(settings);
+    let mut offscreen = layer::offscreen::Offscreen::new(size)?;
+
+    offscreen.render(&shield.draw(value), 1.0);
+    layer::offscreen::write_png(out, size, &offscreen.pixels())
+}
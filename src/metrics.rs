@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Counters shared by the threads of the app. They show how much bursts of
+// events are coalesced: many events received should only turn into a few sink
+// queries and even fewer frames drawn.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    events: AtomicU64,
+    queries: AtomicU64,
+    frames: AtomicU64,
+}
+
+impl Metrics {
+    pub fn event_received(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sink_queried(&self) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn frame_drawn(&self) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> String {
+        format!(
+            "{} events received, {} sink queries, {} frames drawn",
+            self.events.load(Ordering::Relaxed),
+            self.queries.load(Ordering::Relaxed),
+            self.frames.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,437 @@
+use anyhow::{Result, anyhow};
+use crossbeam::channel::Sender;
+use std::collections::{HashMap, HashSet};
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{OwnedValue, Value};
+
+use crate::app::Event;
+use crate::config::Profile;
+
+const NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+// Hints of the notifications sent by OSD scripts: the level in percent, and a
+// tag shared by the notifications that replace each other.
+const VALUE: &str = "value";
+const SYNCHRONOUS: &str = "x-canonical-private-synchronous";
+
+// Reasons given to NotificationClosed by the specification: the notification
+// expired, or was closed by a call to CloseNotification.
+const EXPIRED: u32 = 1;
+const CLOSED_BY_CALL: u32 = 3;
+
+// Shield isn't a notification daemon, only the notifications carrying a level
+// are shown, as a badge with the profile matching their icon or tag. The
+// others are accepted and dropped without being kept track of. The expiration
+// asked by the sender is ignored, the badge stays for the timeout of its
+// profile and the notifications it showed expire when it hides.
+pub struct Server {
+    sender: Sender<Event>,
+    // Ids are never 0, the specification reserves it for new notifications.
+    next: u32,
+    tags: HashMap<String, u32>,
+    // Ids shown since the badge appeared and not closed yet, only those can
+    // be closed.
+    open: HashSet<u32>,
+    // Id of the notification on screen, it can be closed by its sender.
+    shown: Option<u32>,
+}
+
+// The service stops with the connection, so it has to be kept for as long as
+// notifications are received.
+pub struct Notifications {
+    connection: Connection,
+}
+
+impl Notifications {
+    // The badge hid, the notifications it showed are closed as expired.
+    pub fn expire(&self) {
+        let expired = self
+            .connection
+            .object_server()
+            .interface::<_, Server>(PATH)
+            .and_then(|server| {
+                let ids = server.get_mut().expire();
+                for id in ids {
+                    pollster::block_on(Server::notification_closed(
+                        server.signal_emitter(),
+                        id,
+                        EXPIRED,
+                    ))?;
+                }
+                Ok(())
+            });
+
+        if let Err(err) = expired {
+            eprintln!("Notifications couldn't be expired: {err:?}");
+        }
+    }
+}
+
+// Owns the name on the session bus.
+pub fn serve(sender: Sender<Event>) -> Result<Notifications> {
+    serve_on(Builder::session()?, sender)
+}
+
+fn serve_on(builder: Builder<'_>, sender: Sender<Event>) -> Result<Notifications> {
+    let server = Server {
+        sender,
+        next: 1,
+        tags: HashMap::new(),
+        open: HashSet::new(),
+        shown: None,
+    };
+
+    let connection = builder
+        .serve_at(PATH, server)?
+        .name(NAME)?
+        .build()
+        .map_err(|err| match err {
+            zbus::Error::NameTaken => anyhow!("Another notification server owns {NAME}"),
+            err => anyhow::Error::from(err),
+        })?;
+    Ok(Notifications { connection })
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl Server {
+    fn get_capabilities(&self) -> Vec<String> {
+        vec![SYNCHRONOUS.to_string()]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        _app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        _summary: String,
+        _body: String,
+        _actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        let tag = hints.get(SYNCHRONOUS).and_then(|tag| text(tag));
+        let id = self.id(replaces_id, tag.as_deref());
+
+        let Some(level) = hints.get(VALUE).and_then(|value| level(value)) else {
+            return id;
+        };
+        let icon = hints
+            .get("image-path")
+            .and_then(|path| text(path))
+            .unwrap_or(app_icon);
+        let profile = profile(&icon, tag.as_deref());
+
+        if self.sender.send(Event::Notified(profile, level)).is_ok() {
+            if let Some(tag) = tag {
+                self.tags.insert(tag, id);
+            }
+            self.open.insert(id);
+            self.shown = Some(id);
+        }
+        id
+    }
+
+    async fn close_notification(
+        &mut self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        if !self.open.remove(&id) {
+            return Err(zbus::fdo::Error::InvalidArgs(format!(
+                "No notification has the id {id}"
+            )));
+        }
+
+        if self.shown == Some(id) {
+            self.shown = None;
+            let _ = self.sender.send(Event::Hide);
+        }
+        Self::notification_closed(&emitter, id, CLOSED_BY_CALL).await?;
+        Ok(())
+    }
+
+    #[zbus(out_args("name", "vendor", "version", "spec_version"))]
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            "shield".to_string(),
+            "8roken".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            "1.2".to_string(),
+        )
+    }
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+}
+
+impl Server {
+    // A notification replaces the one it names, or the last one shown with the
+    // same tag, and keeps its id.
+    fn id(&mut self, replaces_id: u32, tag: Option<&str>) -> u32 {
+        let tagged = tag.and_then(|tag| self.tags.get(tag)).copied();
+        let id = if replaces_id != 0 {
+            replaces_id
+        } else if let Some(id) = tagged {
+            id
+        } else {
+            let id = self.next;
+            self.next = self.next.checked_add(1).unwrap_or(1);
+            id
+        };
+
+        id
+    }
+
+    // Forgets every notification shown, returns their ids to be signaled.
+    fn expire(&mut self) -> Vec<u32> {
+        self.shown = None;
+        self.tags.clear();
+        let mut ids: Vec<u32> = self.open.drain().collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+// Senders don't agree on the type of the value, notify-send sends an int32,
+// other tools a byte or an unsigned integer. Levels above 100% are kept, the
+// volume can be boosted past it.
+fn level(value: &Value) -> Option<f32> {
+    let percent = match value {
+        Value::Value(inner) => return level(inner),
+        Value::U8(value) => *value as f64,
+        Value::I16(value) => *value as f64,
+        Value::U16(value) => *value as f64,
+        Value::I32(value) => *value as f64,
+        Value::U32(value) => *value as f64,
+        Value::I64(value) => *value as f64,
+        Value::U64(value) => *value as f64,
+        Value::F64(value) => *value,
+        _ => return None,
+    };
+
+    Some((percent.max(0.0) / 100.0) as f32)
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::Value(inner) => text(inner),
+        Value::Str(text) => Some(text.to_string()),
+        _ => None,
+    }
+}
+
+// The icon names of the freedesktop naming specification tell the kind of
+// level, e.g. audio-volume-muted or microphone-sensitivity-high. Other levels,
+// like the brightness, are shown with the volume profile.
+fn profile(icon: &str, tag: Option<&str>) -> Profile {
+    let names = [icon, tag.unwrap_or_default()];
+    let microphone =
+        |name: &&str| name.starts_with("microphone-") || name.starts_with("audio-input-");
+
+    if names.iter().any(microphone) {
+        Profile::Microphone
+    } else if icon.contains("muted") {
+        Profile::Mute
+    } else {
+        Profile::Volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+    use std::time::Duration;
+    use zbus::blocking::Proxy;
+
+    const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir={dir}</listen>
+  <policy context="default">
+    <allow send_destination="*"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+    // A bus of its own, so the test neither needs a session nor takes the
+    // name from the notification daemon of the desktop.
+    struct Bus {
+        daemon: Child,
+        dir: PathBuf,
+        address: String,
+    }
+
+    impl Bus {
+        // None when dbus-daemon isn't installed.
+        fn start() -> Option<Bus> {
+            let dir = std::env::temp_dir().join(format!("shield-bus-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let config = dir.join("bus.conf");
+            let content = BUS_CONFIG.replace("{dir}", dir.to_str().unwrap());
+            std::fs::write(&config, content).unwrap();
+
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let mut address = String::new();
+            let stdout = daemon.stdout.take().unwrap();
+            BufReader::new(stdout).read_line(&mut address).unwrap();
+
+            Some(Bus {
+                daemon,
+                dir,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Builder<'static> {
+            Builder::address(self.address.as_str()).unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn notify(proxy: &Proxy, replaces_id: u32, icon: &str, hints: &[(&str, Value)]) -> u32 {
+        let hints: HashMap<&str, &Value> = hints.iter().map(|(key, value)| (*key, value)).collect();
+        proxy
+            .call(
+                "Notify",
+                &(
+                    "pamixer",
+                    replaces_id,
+                    icon,
+                    "Volume",
+                    "",
+                    Vec::<&str>::new(),
+                    hints,
+                    -1,
+                ),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn levels_are_shown_with_the_profile_of_their_icon() {
+        let Some(bus) = Bus::start() else {
+            assert!(
+                std::env::var_os("CI").is_none(),
+                "dbus-daemon is needed to test the notifications"
+            );
+            eprintln!("Skipped, dbus-daemon isn't installed");
+            return;
+        };
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let server = serve_on(bus.connect(), sender).unwrap();
+        let client = bus.connect().build().unwrap();
+        let proxy = Proxy::new(&client, NAME, PATH, NAME).unwrap();
+        let next = || receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let first = notify(
+            &proxy,
+            0,
+            "audio-volume-medium",
+            &[
+                (VALUE, Value::I32(42)),
+                (SYNCHRONOUS, Value::from("volume")),
+            ],
+        );
+        assert!(matches!(next(), Event::Notified(Profile::Volume, level) if level == 0.42));
+
+        // Notifications sharing the tag replace each other.
+        let second = notify(
+            &proxy,
+            0,
+            "audio-volume-muted",
+            &[(VALUE, Value::U8(0)), (SYNCHRONOUS, Value::from("volume"))],
+        );
+        assert_eq!(first, second);
+        assert!(matches!(next(), Event::Notified(Profile::Mute, _)));
+
+        let third = notify(
+            &proxy,
+            0,
+            "microphone-sensitivity-high",
+            &[(VALUE, Value::U32(80))],
+        );
+        assert_ne!(third, first);
+        assert!(matches!(next(), Event::Notified(Profile::Microphone, _)));
+
+        // Notifications without a level aren't shown.
+        notify(&proxy, 0, "mail-unread", &[]);
+        let _: () = proxy.call("CloseNotification", &third).unwrap();
+        assert!(matches!(next(), Event::Hide));
+
+        // Ids that were never given out, or were closed already, are refused.
+        let closed: zbus::Result<()> = proxy.call("CloseNotification", &third);
+        assert!(closed.is_err());
+        let unknown: zbus::Result<()> = proxy.call("CloseNotification", &(third + 100));
+        assert!(unknown.is_err());
+
+        // Notifications expire with the badge, their tag starts a new one.
+        notify(
+            &proxy,
+            0,
+            "audio-volume-high",
+            &[(VALUE, Value::U8(60)), (SYNCHRONOUS, Value::from("volume"))],
+        );
+        assert!(matches!(next(), Event::Notified(Profile::Volume, _)));
+        server.expire();
+        let expired: zbus::Result<()> = proxy.call("CloseNotification", &first);
+        assert!(expired.is_err());
+        let fifth = notify(
+            &proxy,
+            0,
+            "audio-volume-high",
+            &[(VALUE, Value::U8(70)), (SYNCHRONOUS, Value::from("volume"))],
+        );
+        assert_ne!(fifth, first);
+    }
+
+    #[test]
+    fn microphones_are_told_by_the_prefix_of_their_icon() {
+        assert_eq!(
+            profile("audio-input-microphone-muted", None),
+            Profile::Microphone
+        );
+        assert_eq!(
+            profile("audio-volume-high", Some("microphone-volume")),
+            Profile::Microphone
+        );
+        // Names merely containing "mic" aren't microphones.
+        assert_eq!(profile("dynamic-range", None), Profile::Volume);
+        assert_eq!(profile("audio-volume-muted", Some("mimic")), Profile::Mute);
+    }
+
+    #[test]
+    fn levels_are_read_from_any_integer() {
+        assert_eq!(level(&Value::I32(50)), Some(0.5));
+        assert_eq!(level(&Value::U8(100)), Some(1.0));
+        assert_eq!(level(&Value::Value(Box::new(Value::U32(150)))), Some(1.5));
+        assert_eq!(level(&Value::I32(-3)), Some(0.0));
+        assert_eq!(level(&Value::from("50")), None);
+    }
+}
@@ -0,0 +1,201 @@
+use parley::GlyphRun;
+use skrifa::instance::NormalizedCoord;
+use vello::kurbo::{Affine, BezPath, Rect, Shape, Stroke};
+use vello::peniko::{Brush, BrushRef, Color, Fill, Font, Mix};
+use vello::{Glyph, Scene};
+
+// Curves are flattened within this distance, in pixels, when shapes are
+// recorded for the CPU.
+const TOLERANCE: f64 = 0.1;
+
+// What the shield draws, recorded once for both renderers: vello renders the
+// scene on the GPU, the commands are rasterized on the CPU when there is no GPU
+// to render with. Both are in logical pixels.
+#[derive(Clone, Default)]
+pub struct Drawing {
+    scene: Scene,
+    commands: Vec<Command>,
+}
+
+#[derive(Clone)]
+pub enum Command {
+    Fill {
+        transform: Affine,
+        brush: Brush,
+        path: BezPath,
+    },
+    Stroke {
+        transform: Affine,
+        width: f64,
+        brush: Brush,
+        path: BezPath,
+    },
+    // A rounded rectangle blurred by a gaussian, the blur is its standard
+    // deviation.
+    BlurredRect {
+        transform: Affine,
+        rect: Rect,
+        color: Color,
+        radius: f64,
+        blur: f64,
+    },
+    // Glyphs are drawn from the outlines of the font, at the position of each
+    // glyph and through the glyph transform, e.g. the skew of a synthetic italic.
+    Glyphs {
+        transform: Affine,
+        glyph_transform: Option<Affine>,
+        font: Font,
+        size: f32,
+        coords: Vec<NormalizedCoord>,
+        brush: Brush,
+        glyphs: Vec<Glyph>,
+    },
+    // Everything until the matching PopClip is clipped to the path.
+    PushClip {
+        transform: Affine,
+        path: BezPath,
+    },
+    PopClip,
+}
+
+impl Drawing {
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn fill<'b>(
+        &mut self,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        shape: &impl Shape,
+    ) {
+        let brush = brush.into();
+        self.scene
+            .fill(Fill::NonZero, transform, brush, None, shape);
+        self.commands.push(Command::Fill {
+            transform,
+            brush: brush.to_owned(),
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn stroke<'b>(
+        &mut self,
+        width: f64,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        shape: &impl Shape,
+    ) {
+        let brush = brush.into();
+        self.scene
+            .stroke(&Stroke::new(width), transform, brush, None, shape);
+        self.commands.push(Command::Stroke {
+            transform,
+            width,
+            brush: brush.to_owned(),
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn blurred_rect(
+        &mut self,
+        transform: Affine,
+        rect: Rect,
+        color: Color,
+        radius: f64,
+        blur: f64,
+    ) {
+        self.scene
+            .draw_blurred_rounded_rect(transform, rect, color, radius, blur);
+        self.commands.push(Command::BlurredRect {
+            transform,
+            rect,
+            color,
+            radius,
+            blur,
+        });
+    }
+
+    pub fn push_clip(&mut self, transform: Affine, shape: &impl Shape) {
+        self.scene.push_layer(Mix::Clip, 1.0, transform, shape);
+        self.commands.push(Command::PushClip {
+            transform,
+            path: shape.to_path(TOLERANCE),
+        });
+    }
+
+    pub fn pop_clip(&mut self) {
+        self.scene.pop_layer();
+        self.commands.push(Command::PopClip);
+    }
+
+    // Lays the glyphs of the run out from its baseline, moved down by the offset.
+    pub fn glyphs(&mut self, glyph_run: &GlyphRun<'_, Brush>, transform: Affine, offset: f32) {
+        let style = glyph_run.style();
+        let run = glyph_run.run();
+        let glyph_transform = run
+            .synthesis()
+            .skew()
+            .map(|angle| Affine::skew(angle.to_radians().tan() as f64, 0.0));
+
+        let mut x = glyph_run.offset();
+        let y = glyph_run.baseline() + offset;
+        let glyphs: Vec<Glyph> = glyph_run
+            .glyphs()
+            .map(|glyph| {
+                let positioned = Glyph {
+                    id: glyph.id as u32,
+                    x: x + glyph.x,
+                    y: y - glyph.y,
+                };
+                x += glyph.advance;
+                positioned
+            })
+            .collect();
+
+        self.scene
+            .draw_glyphs(run.font())
+            .brush(&style.brush)
+            .hint(true)
+            .transform(transform)
+            .glyph_transform(glyph_transform)
+            .font_size(run.font_size())
+            .normalized_coords(run.normalized_coords())
+            .draw(Fill::NonZero, glyphs.iter().copied());
+        self.commands.push(Command::Glyphs {
+            transform,
+            glyph_transform,
+            font: run.font().clone(),
+            size: run.font_size(),
+            coords: run
+                .normalized_coords()
+                .iter()
+                .map(|coord| coord.to_coord())
+                .collect(),
+            brush: style.brush.clone(),
+            glyphs,
+        });
+    }
+}
+
+// Variation coordinates as skrifa reads them, whichever way the layout gives
+// them out.
+trait ToCoord {
+    fn to_coord(&self) -> NormalizedCoord;
+}
+
+impl ToCoord for i16 {
+    fn to_coord(&self) -> NormalizedCoord {
+        NormalizedCoord::from_bits(*self)
+    }
+}
+
+impl ToCoord for NormalizedCoord {
+    fn to_coord(&self) -> NormalizedCoord {
+        *self
+    }
+}
@@ -1,81 +1,218 @@
 use parley::*;
 use std::sync::Arc;
+use std::time::Instant;
 use vello::{
-    kurbo::Affine,
-    peniko::color::palette,
-    peniko::{Brush, Color, Fill},
-    *,
+    kurbo::{Affine, Rect, Vec2},
+    peniko,
+    peniko::Brush,
 };
 
-use crate::config::Settings;
+use crate::appearance::Scheme;
+use crate::config::{Gradient, Profile, Settings};
+
+mod drawing;
+mod transition;
+pub use drawing::{Command, Drawing};
+use transition::Transition;
+
+#[cfg(test)]
+mod tests;
 
 pub struct Shield {
+    // Settings the shield is drawn with, the configured ones resolved for the
+    // profile of the event shown and the scheme of the system.
     settings: Arc<Settings>,
+    configured: Arc<Settings>,
+    scheme: Option<Scheme>,
+    profile: Profile,
     contexts: (LayoutContext<Brush>, FontContext),
+    transition: Option<Transition>,
 }
 
 impl Shield {
     pub fn new(settings: Arc<Settings>) -> Shield {
         let font_cx = FontContext::new();
         let layout_cx = LayoutContext::new();
-        Shield {
-            settings,
+        let mut shield = Shield {
+            settings: settings.clone(),
+            configured: settings,
+            scheme: None,
+            profile: Profile::Volume,
             contexts: (layout_cx, font_cx),
+            transition: None,
+        };
+        shield.resolve();
+        shield
+    }
+
+    pub fn settings(&self) -> &Arc<Settings> {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: Arc<Settings>) {
+        self.configured = settings;
+        self.resolve();
+    }
+
+    pub fn set_scheme(&mut self, scheme: Scheme) {
+        self.scheme = Some(scheme);
+        self.resolve();
+    }
+
+    // Returns whether the profile changed, the layer then needs the settings
+    // of the new one.
+    pub fn set_profile(&mut self, profile: Profile) -> bool {
+        let changed = profile != self.profile;
+        self.profile = profile;
+        if changed {
+            self.resolve();
+        }
+        changed
+    }
+
+    // The scheme only applies to settings following the system, the others
+    // have a scheme of their own or no palette at all.
+    fn resolve(&mut self) {
+        let settings = self.configured.resolve(self.profile);
+        self.settings = match self.scheme.filter(|_| *settings.follow_system()) {
+            Some(scheme) => Arc::new(settings.with_scheme(scheme)),
+            None => settings,
+        };
+    }
+
+    // The first value is shown as is, every subsequent value is animated
+    // from whatever is currently displayed.
+    pub fn animate_to(&mut self, volume: f32) {
+        match self.transition.as_mut() {
+            Some(transition) => {
+                transition.retarget(volume, *self.settings.transition(), Instant::now())
+            }
+            None => self.transition = Some(Transition::settled(volume)),
         }
     }
 
-    pub fn scene(&mut self, volume: f32) -> Scene {
-        let mut scene = Scene::new();
+    pub fn animating(&self) -> bool {
+        self.transition
+            .is_some_and(|transition| !transition.is_done(Instant::now()))
+    }
+
+    // Draws the value the transition is at right now.
+    pub fn frame(&mut self) -> Drawing {
+        let volume = self
+            .transition
+            .map_or(0.0, |transition| transition.value_at(Instant::now()));
+        self.draw(volume)
+    }
+
+    pub fn draw(&mut self, volume: f32) -> Drawing {
+        let mut drawing = Drawing::default();
+        let padding = self.settings.padding();
+        let transform = Affine::translate((padding, padding));
+
+        self.backdrop(&mut drawing, transform);
+
+        if *self.settings.bar().visible() {
+            self.bar(&mut drawing, transform, volume);
+        }
+
+        if *self.settings.text().visible() {
+            self.text(&mut drawing, transform, volume);
+        }
+
+        drawing
+    }
+
+    fn text(&mut self, drawing: &mut Drawing, transform: Affine, volume: f32) {
         let layout = self.layout(format!("{:.0}%", volume * 100.0));
-        let size = self.settings.size();
-        let radius = self.settings.radius();
-        scene.fill(
-            vello::peniko::Fill::NonZero,
-            Affine::IDENTITY,
-            self.settings.background_color(),
-            None,
-            &Rect::new(0.0, 0.0, size.0.into(), size.1.into()).to_rounded_rect(radius.clone()),
-        );
+        let offset = *self.settings.text().offset();
 
         for line in layout.lines() {
             for item in line.items() {
                 let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
                     continue;
                 };
-                let style = glyph_run.style();
-                let mut x = glyph_run.offset();
-                let y = glyph_run.baseline() + 85.0;
-                let run = glyph_run.run();
-                let font = run.font();
-                let font_size = run.font_size();
-                let synthesis = run.synthesis();
-                let glyph_xform = synthesis
-                    .skew()
-                    .map(|angle| Affine::skew(angle.to_radians().tan() as f64, 0.0));
-
-                scene
-                    .draw_glyphs(font)
-                    .brush(&style.brush)
-                    .hint(true)
-                    .glyph_transform(glyph_xform)
-                    .font_size(font_size)
-                    .normalized_coords(run.normalized_coords())
-                    .draw(
-                        Fill::NonZero,
-                        glyph_run.glyphs().map(|glyph| {
-                            let gx = x + glyph.x;
-                            let gy = y - glyph.y;
-                            x += glyph.advance;
-                            vello::Glyph {
-                                id: glyph.id as u32,
-                                x: gx,
-                                y: gy,
-                            }
-                        }),
-                    );
+                drawing.glyphs(&glyph_run, transform, offset);
             }
         }
-        scene
+    }
+
+    // The filled part of the bar is capped at 100% even when the volume is
+    // amplified past it.
+    fn bar(&self, drawing: &mut Drawing, transform: Affine, volume: f32) {
+        let bar = self.settings.bar();
+        let size = self.settings.size();
+        let (width, height) = (f64::from(size.0), f64::from(size.1));
+        let margin = *bar.margin();
+        let track = Rect::new(
+            margin,
+            height - margin - bar.height(),
+            width - margin,
+            height - margin,
+        );
+        let radius = bar.height() / 2.0;
+
+        drawing.fill(transform, bar.track(), &track.to_rounded_rect(radius));
+
+        let filled = track.with_size((
+            track.width() * volume.clamp(0.0, 1.0) as f64,
+            track.height(),
+        ));
+        if filled.width() > 0.0 {
+            drawing.fill(transform, bar.color(), &filled.to_rounded_rect(radius));
+        }
+    }
+
+    // Draws everything behind the text: shadow, background, inner glow and border.
+    fn backdrop(&self, drawing: &mut Drawing, transform: Affine) {
+        let size = self.settings.size();
+        let radius = *self.settings.radius();
+        let rect = Rect::new(0.0, 0.0, size.0.into(), size.1.into());
+        let shape = rect.to_rounded_rect(radius);
+
+        if let Some(shadow) = self.settings.shadow() {
+            drawing.blurred_rect(
+                transform * Affine::translate(*shadow.offset()),
+                rect,
+                *shadow.color(),
+                radius,
+                *shadow.blur(),
+            );
+        }
+
+        match self.settings.gradient() {
+            Some(gradient) => {
+                drawing.fill(transform, &background_gradient(gradient, rect), &shape);
+            }
+            None => {
+                drawing.fill(transform, self.settings.background_color(), &shape);
+            }
+        }
+
+        // The glow is made of strokes layered on top of each other along the edge,
+        // each narrower than the previous one, so the color fades out toward the center.
+        // The clip keeps the outer half of the strokes from spilling outside the shield.
+        if let Some(glow) = self.settings.glow() {
+            const STEPS: usize = 8;
+            let color = glow.color().multiply_alpha(1.0 / STEPS as f32);
+
+            drawing.push_clip(transform, &shape);
+            for step in 0..STEPS {
+                let width = glow.width() * 2.0 * (1.0 - step as f64 / STEPS as f64);
+                drawing.stroke(width, transform, color, &shape);
+            }
+            drawing.pop_clip();
+        }
+
+        if let Some(border) = self.settings.border() {
+            let width = *border.width();
+            let inset = rect.inset(-width / 2.0);
+            drawing.stroke(
+                width,
+                transform,
+                border.color(),
+                &inset.to_rounded_rect((radius - width / 2.0).max(0.0)),
+            );
+        }
     }
 
     fn layout(&mut self, text: String) -> Layout<Brush> {
@@ -87,7 +224,7 @@ impl Shield {
         builder.push_default(StyleProperty::FontStack(FontStack::Single(
             FontFamily::Generic(GenericFamily::UiMonospace),
         )));
-        builder.push_default(StyleProperty::FontSize(112.0));
+        builder.push_default(StyleProperty::FontSize(*self.settings.text().size()));
         builder.push_default(StyleProperty::FontWeight(FontWeight::NORMAL));
         builder.push_default(StyleProperty::Brush(
             self.settings.foreground_color().to_owned().into(),
@@ -97,8 +234,33 @@ impl Shield {
         // Build the builder into a Layout
         let mut layout = builder.build(&text);
         layout.break_all_lines(None);
-        layout.align(Some(300.0), Alignment::Middle, AlignmentOptions::default());
+        layout.align(
+            Some(self.settings.size().0 as f32),
+            Alignment::Middle,
+            AlignmentOptions::default(),
+        );
 
         layout
     }
 }
+
+fn background_gradient(gradient: &Gradient, rect: Rect) -> peniko::Gradient {
+    let center = rect.center();
+
+    match gradient {
+        Gradient::Linear { from, to, angle } => {
+            // Project the rect on the direction of the gradient so the gradient
+            // starts and ends on the edges of the shield, whatever the angle is.
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let half = (rect.width() * cos.abs() + rect.height() * sin.abs()) / 2.0;
+            let direction = Vec2::new(cos, sin) * half;
+
+            peniko::Gradient::new_linear(center - direction, center + direction)
+                .with_stops([*from, *to])
+        }
+        Gradient::Radial { from, to } => {
+            let radius = rect.width().max(rect.height()) / 2.0;
+            peniko::Gradient::new_radial(center, radius as f32).with_stops([*from, *to])
+        }
+    }
+}
@@ -0,0 +1,187 @@
+// Golden-image tests: the drawings of the shield are rasterized on the CPU and
+// compared with the reference images checked in under tests/golden.
+//
+// After an intended visual change, re-bless the references with:
+//
+//     cargo bless
+//
+// which runs the ignored `bless` test and overwrites every reference image.
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::Shield;
+use crate::config::{Output, Profile, Renderer, Settings};
+use crate::layer::offscreen::{self, Offscreen};
+use vello::peniko::Color;
+
+// A channel can be off by this much before the pixel is considered different;
+// software rasterizers don't always agree on the last bit of anti-aliasing.
+const CHANNEL_TOLERANCE: u8 = 3;
+// Share of the pixels that can be different before the image is rejected.
+const PIXEL_TOLERANCE: f64 = 0.005;
+
+const VALUES: [f32; 3] = [0.0, 0.42, 1.0];
+
+// Every theme is rendered with each value. The default settings are also
+// rendered at a smaller size to catch layouts that only hold at 300x200.
+const THEMES: [(&str, &str); 6] = [
+    ("default", ""),
+    (
+        "default-small",
+        "[frame.size]\nwidth = 160\nheight = 100\n[text]\nsize = 56\noffset = 42\n",
+    ),
+    ("macos-light", "theme = \"macos-light\"\n"),
+    ("macos-dark", "theme = \"macos-dark\"\n"),
+    ("high-contrast", "theme = \"high-contrast\"\n"),
+    ("minimal-bar", "theme = \"minimal-bar\"\n"),
+];
+
+struct Image {
+    size: (u32, u32),
+    pixels: Vec<u8>,
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+// Renders every case of the matrix and hands it to the callback with its name.
+fn render_all(mut check: impl FnMut(&str, Image)) {
+    for (theme, toml) in THEMES {
+        let settings = Arc::new(Settings::from_toml(toml).unwrap());
+        let size = settings.surface_size();
+        let mut shield = Shield::new(settings);
+        let mut offscreen = Offscreen::new(size).unwrap();
+
+        for value in VALUES {
+            offscreen.render(&shield.draw(value), 1.0);
+            let name = format!("{theme}-{:03}", (value * 100.0).round() as u32);
+            let pixels = offscreen.pixels();
+            check(&name, Image { size, pixels });
+        }
+    }
+}
+
+fn read_png(path: &Path) -> Option<Image> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{path:?} should be an 8-bit RGBA image"
+    );
+    pixels.truncate(info.buffer_size());
+
+    Some(Image {
+        size: (info.width, info.height),
+        pixels,
+    })
+}
+
+// Returns the share of pixels that differ by more than the channel tolerance.
+fn difference(expected: &Image, actual: &Image) -> f64 {
+    let different = expected
+        .pixels
+        .chunks(4)
+        .zip(actual.pixels.chunks(4))
+        .filter(|(expected, actual)| {
+            expected
+                .iter()
+                .zip(actual.iter())
+                .any(|(e, a)| e.abs_diff(*a) > CHANNEL_TOLERANCE)
+        })
+        .count();
+
+    different as f64 / (expected.pixels.len() / 4) as f64
+}
+
+#[test]
+fn drawings_match_golden_images() {
+    let dir = golden_dir();
+    let failures_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden");
+    let mut failures = vec![];
+
+    render_all(|name, actual| {
+        let path = dir.join(format!("{name}.png"));
+        let Some(expected) = read_png(&path) else {
+            failures.push(format!(
+                "{name}: no reference image at {path:?}, bless and commit the references"
+            ));
+            return;
+        };
+
+        let failure = if expected.size != actual.size {
+            format!(
+                "{name}: size is {:?}, expected {:?}",
+                actual.size, expected.size
+            )
+        } else {
+            let difference = difference(&expected, &actual);
+            if difference <= PIXEL_TOLERANCE {
+                return;
+            }
+            format!("{name}: {:.2}% of the pixels differ", difference * 100.0)
+        };
+
+        // Keep the rendered image around so it can be compared with the reference.
+        std::fs::create_dir_all(&failures_dir).unwrap();
+        let actual_path = failures_dir.join(format!("{name}.png"));
+        offscreen::write_png(&actual_path, actual.size, &actual.pixels).unwrap();
+        failures.push(format!("{failure}, rendered image: {actual_path:?}"));
+    });
+
+    assert!(
+        failures.is_empty(),
+        "drawings don't match their golden images, run `cargo bless` if the change is intended:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+#[ignore = "overwrites the golden images, run with `cargo bless`"]
+fn bless() {
+    let dir = golden_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+
+    render_all(|name, image| {
+        offscreen::write_png(&dir.join(format!("{name}.png")), image.size, &image.pixels).unwrap();
+    });
+}
+
+#[test]
+fn events_are_drawn_with_their_profile() {
+    let settings = Settings::from_toml(
+        "[color]\nbackground = \"black\"\n[profile.mute.color]\nbackground = \"red\"\n",
+    )
+    .unwrap();
+    let mut shield = Shield::new(Arc::new(settings));
+    let background = |shield: &Shield| shield.settings().background_color().to_rgba8();
+
+    assert!(!shield.set_profile(Profile::Volume));
+    assert!(shield.set_profile(Profile::Mute));
+    assert_eq!(background(&shield), Color::from_rgb8(255, 0, 0).to_rgba8());
+
+    // Device has no profile, it's drawn with the configured settings.
+    assert!(shield.set_profile(Profile::Device));
+    assert_eq!(background(&shield), Color::from_rgb8(0, 0, 0).to_rgba8());
+}
+
+#[test]
+fn profiles_cannot_change_the_global_keys() {
+    let settings = Settings::from_toml(
+        "renderer = \"gpu\"\n[profile.mute]\nrenderer = \"cpu\"\noutput = \"all\"\n",
+    )
+    .unwrap();
+    let mut shield = Shield::new(Arc::new(settings));
+
+    assert!(shield.set_profile(Profile::Mute));
+    assert_eq!(*shield.settings().renderer(), Renderer::Gpu);
+    assert_eq!(*shield.settings().output(), Output::Focused);
+}
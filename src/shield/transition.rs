@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+
+// Transition eases the value displayed by the shield from where it currently
+// is to the latest value reported by the audio server. When a new value comes in
+// while a transition is still running, it starts over from the value that is
+// currently on screen so the motion never jumps.
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Transition {
+    pub fn settled(value: f32) -> Transition {
+        Transition {
+            from: value,
+            to: value,
+            started: Instant::now(),
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn retarget(&mut self, to: f32, duration: Duration, now: Instant) {
+        self.from = self.value_at(now);
+        self.to = to;
+        self.started = now;
+        self.duration = duration;
+    }
+
+    pub fn value_at(&self, now: Instant) -> f32 {
+        self.from + (self.to - self.from) * ease_out(self.progress(now))
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        self.progress(now) >= 1.0
+    }
+
+    fn progress(&self, now: Instant) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+
+        let elapsed = now.saturating_duration_since(self.started);
+        (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+}
+
+// Cubic ease-out: moves quickly at first and slows down as it reaches the target.
+fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DURATION: Duration = Duration::from_millis(200);
+
+    fn started(now: Instant) -> Transition {
+        let mut transition = Transition::settled(0.25);
+        transition.retarget(1.0, DURATION, now);
+        transition
+    }
+
+    #[test]
+    fn starts_from_the_displayed_value() {
+        let now = Instant::now();
+        let transition = started(now);
+
+        assert_eq!(transition.value_at(now), 0.25);
+        assert!(!transition.is_done(now));
+    }
+
+    #[test]
+    fn eases_out_past_the_midpoint() {
+        let now = Instant::now();
+        let transition = started(now);
+        let value = transition.value_at(now + DURATION / 2);
+
+        // Half of the time covers 87.5% of the way, 0.25 + 0.75 * 0.875.
+        assert_eq!(value, 0.90625);
+    }
+
+    #[test]
+    fn stops_at_the_target_once_the_duration_elapsed() {
+        let now = Instant::now();
+        let transition = started(now);
+
+        assert_eq!(transition.value_at(now + DURATION), 1.0);
+        assert_eq!(transition.value_at(now + DURATION * 3), 1.0);
+        assert!(transition.is_done(now + DURATION));
+    }
+
+    #[test]
+    fn zero_duration_jumps_to_the_target() {
+        let now = Instant::now();
+        let mut transition = Transition::settled(0.25);
+        transition.retarget(0.75, Duration::ZERO, now);
+
+        assert_eq!(transition.value_at(now), 0.75);
+        assert!(transition.is_done(now));
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+// Deadlines the app waits on. Each name has at most one deadline pending,
+// setting it again moves it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    // The shield is hidden once the timeout elapsed.
+    Hide,
+    // Next frame of the transition between two values.
+    Frame,
+    // Changes coming in bursts are applied together once it elapses.
+    Debounce,
+    // Configuration files are read again once they stopped changing.
+    Reload,
+}
+
+// Timers are polled by the loop of the app: it sleeps until the next deadline
+// or the next event, whichever comes first. Deadlines already in the past are
+// simply expired on the next poll.
+#[derive(Debug, Default)]
+pub struct Timers {
+    pending: Vec<(Deadline, Instant)>,
+}
+
+impl Timers {
+    pub fn set(&mut self, deadline: Deadline, at: Instant) {
+        self.cancel(deadline);
+        self.pending.push((deadline, at));
+    }
+
+    pub fn set_after(&mut self, deadline: Deadline, after: Duration) {
+        self.set(deadline, Instant::now() + after);
+    }
+
+    pub fn cancel(&mut self, deadline: Deadline) {
+        self.pending.retain(|(pending, _)| *pending != deadline);
+    }
+
+    pub fn is_set(&self, deadline: Deadline) -> bool {
+        self.pending.iter().any(|(pending, _)| *pending == deadline)
+    }
+
+    // The earliest deadline, if any.
+    pub fn next(&self) -> Option<Instant> {
+        self.pending.iter().map(|(_, at)| *at).min()
+    }
+
+    // Removes the deadlines that elapsed by now and returns them, earliest first.
+    pub fn expired(&mut self, now: Instant) -> Vec<Deadline> {
+        let mut expired: Vec<(Deadline, Instant)> = self
+            .pending
+            .iter()
+            .filter(|(_, at)| *at <= now)
+            .copied()
+            .collect();
+        expired.sort_by_key(|(_, at)| *at);
+        self.pending.retain(|(_, at)| *at > now);
+
+        expired.into_iter().map(|(deadline, _)| deadline).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_a_deadline_again_moves_it() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+
+        timers.set(Deadline::Hide, now + Duration::from_millis(10));
+        timers.set(Deadline::Hide, now + Duration::from_millis(30));
+
+        assert_eq!(timers.next(), Some(now + Duration::from_millis(30)));
+        assert!(timers.expired(now + Duration::from_millis(20)).is_empty());
+        assert_eq!(
+            timers.expired(now + Duration::from_millis(30)),
+            vec![Deadline::Hide]
+        );
+        assert_eq!(timers.next(), None);
+    }
+
+    #[test]
+    fn deadlines_expire_earliest_first() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+
+        timers.set(Deadline::Hide, now + Duration::from_millis(20));
+        timers.set(Deadline::Frame, now + Duration::from_millis(5));
+        timers.set(Deadline::Debounce, now + Duration::from_millis(50));
+
+        assert_eq!(timers.next(), Some(now + Duration::from_millis(5)));
+        assert_eq!(
+            timers.expired(now + Duration::from_millis(20)),
+            vec![Deadline::Frame, Deadline::Hide]
+        );
+        assert!(timers.is_set(Deadline::Debounce));
+    }
+
+    #[test]
+    fn past_deadlines_expire_right_away() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+
+        timers.set(Deadline::Hide, now - Duration::from_millis(100));
+
+        assert_eq!(timers.expired(now), vec![Deadline::Hide]);
+    }
+
+    #[test]
+    fn cancelled_deadlines_never_expire() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+
+        timers.set(Deadline::Hide, now);
+        timers.cancel(Deadline::Hide);
+
+        assert!(!timers.is_set(Deadline::Hide));
+        assert!(timers.expired(now).is_empty());
+    }
+}